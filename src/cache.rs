@@ -0,0 +1,672 @@
+//! Keeping responses for as long as their records say they can be kept
+//! https://tools.ietf.org/html/rfc1035#section-7.4
+//! https://tools.ietf.org/html/rfc2308
+//! https://tools.ietf.org/html/rfc8767
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::records::RData;
+use crate::wire::{DnsHeaderFlags, Message, Question, Rcode, ResourceRecord};
+
+/// How long to wait before asking for an entry to be refreshed again, in
+/// case the last attempt failed.
+/// https://tools.ietf.org/html/rfc8767#section-4
+const REFRESH_RECHECK: Duration = Duration::from_secs(30);
+
+/// How many responses to keep, and for how long.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheConfig {
+	/// How many responses to keep before evicting the least recently used.
+	pub capacity: usize,
+	/// TTLs shorter than this many seconds are raised to it.
+	pub min_ttl: u32,
+	/// TTLs longer than this many seconds are cut down to it.
+	pub max_ttl: u32,
+	/// The most seconds a negative response is kept for.
+	/// https://tools.ietf.org/html/rfc2308#section-5
+	pub max_negative_ttl: u32,
+	/// How many seconds after expiring a response can still be answered
+	/// with while it is refreshed, or 0 to not serve stale responses.
+	/// https://tools.ietf.org/html/rfc8767#section-5
+	pub max_stale: u32,
+	/// The TTL given to the records in stale responses.
+	/// https://tools.ietf.org/html/rfc8767#section-4
+	pub stale_ttl: u32,
+	/// How many times a response has to be used before it is refreshed ahead
+	/// of expiring, or 0 to not prefetch.
+	pub prefetch_hits: u32,
+	/// How much of its TTL a popular response has left, in percent, when it
+	/// is refreshed.
+	pub prefetch_percent: u32,
+}
+
+impl Default for CacheConfig {
+	fn default() -> CacheConfig {
+		CacheConfig {
+			capacity: 1024,
+			min_ttl: 0,
+			max_ttl: 86400,
+			max_negative_ttl: 10800,
+			max_stale: 0,
+			stale_ttl: 30,
+			prefetch_hits: 0,
+			prefetch_percent: 10,
+		}
+	}
+}
+
+#[derive(Debug)]
+struct Entry {
+	/// The response with its TTLs as they were when it was stored.
+	message: Message,
+	stored: Instant,
+	/// How long after being stored the response expires.
+	ttl: Duration,
+	/// When the entry was last used, by the clock in `Entries`.
+	used: u64,
+	/// How many times the entry has been used.
+	hits: u32,
+	/// When a caller was last asked to refresh the entry.
+	refreshing: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+	map: HashMap<Question, Entry>,
+	/// The questions in the map by when they were last used, oldest first.
+	lru: BTreeMap<u64, Question>,
+	/// Counts up each time an entry is used.
+	clock: u64,
+}
+
+impl Entries {
+	fn touch(&mut self, question: &Question) {
+		self.clock += 1;
+		if let Some(entry) = self.map.get_mut(question) {
+			self.lru.remove(&entry.used);
+			entry.used = self.clock;
+			self.lru.insert(self.clock, question.clone());
+		}
+	}
+
+	fn remove(&mut self, question: &Question) {
+		if let Some(entry) = self.map.remove(question) {
+			self.lru.remove(&entry.used);
+		}
+	}
+
+	fn evict_oldest(&mut self) {
+		let oldest = self.lru.values().next().cloned();
+		if let Some(question) = oldest {
+			self.remove(&question);
+		}
+	}
+}
+
+/// A response found in the cache.
+#[derive(Clone, Debug)]
+pub struct Hit {
+	/// The response, with TTLs counted down by the time it has spent in the
+	/// cache.
+	pub message: Message,
+	/// Whether the response has expired, and is only being served because
+	/// `max_stale` allows it.
+	pub stale: bool,
+	/// Whether the caller should look up a new response and insert it,
+	/// because this one has expired or is about to. Only one caller at a
+	/// time is asked to.
+	pub refresh: bool,
+}
+
+/// A thread safe cache of responses, keyed by the name, type and class they
+/// answer.
+#[derive(Debug)]
+pub struct Cache {
+	config: CacheConfig,
+	entries: Mutex<Entries>,
+}
+
+impl Cache {
+	pub fn new(config: CacheConfig) -> Cache {
+		Cache {
+			config,
+			entries: Mutex::new(Entries::default()),
+		}
+	}
+
+	pub fn config(&self) -> &CacheConfig {
+		&self.config
+	}
+
+	/// How many responses are stored, including any that have expired but
+	/// not yet been removed, or are being kept to serve stale.
+	pub fn len(&self) -> usize {
+		self.entries.lock().unwrap().map.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub fn clear(&self) {
+		*self.entries.lock().unwrap() = Entries::default();
+	}
+
+	/// The stored response to `question`, unless it has expired for longer
+	/// than `max_stale` allows.
+	pub fn get(&self, question: &Question) -> Option<Hit> {
+		self.get_at(question, Instant::now())
+	}
+
+	fn get_at(&self, question: &Question, now: Instant) -> Option<Hit> {
+		let config = &self.config;
+		let mut entries = self.entries.lock().unwrap();
+		let entry = entries.map.get_mut(question)?;
+		let age = now.duration_since(entry.stored);
+		let stale = age >= entry.ttl;
+		if stale && age >= entry.ttl + Duration::from_secs(config.max_stale as u64) {
+			entries.remove(question);
+			return None;
+		}
+
+		entry.hits = entry.hits.saturating_add(1);
+		let due = stale
+			|| (config.prefetch_hits > 0
+				&& entry.hits >= config.prefetch_hits
+				&& (entry.ttl - age) * 100 <= entry.ttl * config.prefetch_percent);
+		// A prefetch that hasn't come back by the time the entry expires has
+		// probably failed, so going stale is always worth another try.
+		let expires = entry.stored + entry.ttl;
+		let refresh = due
+			&& match entry.refreshing {
+				Some(since) => {
+					now.duration_since(since) >= REFRESH_RECHECK || (stale && since < expires)
+				}
+				None => true,
+			};
+		if refresh {
+			entry.refreshing = Some(now);
+		}
+
+		let mut message = entry.message.clone();
+		let elapsed = age.as_secs() as u32;
+		for rr in records_mut(&mut message) {
+			rr.ttl = if stale {
+				config.stale_ttl
+			} else {
+				rr.ttl.saturating_sub(elapsed)
+			};
+		}
+		entries.touch(question);
+		Some(Hit {
+			message,
+			stale,
+			refresh,
+		})
+	}
+
+	/// Store a response under its question, replacing any stored before.
+	/// Responses that can't be cached, like errors, referrals and negative
+	/// responses without an SOA record, are ignored, leaving any stale
+	/// response there was to keep being served.
+	pub fn insert(&self, response: &Message) {
+		self.insert_at(response, Instant::now())
+	}
+
+	fn insert_at(&self, response: &Message, now: Instant) {
+		let (message, ttl) = match self.prepare(response) {
+			Some(prepared) => prepared,
+			None => return,
+		};
+		let question = message.questions[0].clone();
+
+		let mut entries = self.entries.lock().unwrap();
+		entries.remove(&question);
+		if self.config.capacity == 0 {
+			return;
+		}
+		while entries.map.len() >= self.config.capacity {
+			entries.evict_oldest();
+		}
+		let entry = Entry {
+			message,
+			stored: now,
+			ttl: Duration::from_secs(ttl as u64),
+			used: 0,
+			hits: 0,
+			refreshing: None,
+		};
+		entries.map.insert(question.clone(), entry);
+		entries.touch(&question);
+	}
+
+	/// A copy of `response` to store, with its TTLs clamped and without its
+	/// OPT record, which only applies to the hop it came over, along with how
+	/// many seconds it can be kept.
+	fn prepare(&self, response: &Message) -> Option<(Message, u32)> {
+		if response.questions.len() != 1
+			|| response.header.flags().contains(DnsHeaderFlags::TRUNCATED)
+		{
+			return None;
+		}
+		let rcode = response.rcode();
+		if rcode != Rcode::NoError && rcode != Rcode::NXDomain {
+			return None;
+		}
+
+		let positive = response.answers.iter().map(|rr| rr.ttl).min();
+		// https://tools.ietf.org/html/rfc2308#section-5
+		let negative = response.authorities.iter().find_map(|rr| match rr.rdata {
+			RData::SOA { minimum, .. } => Some(rr.ttl.min(minimum)),
+			_ => None,
+		});
+		if rcode == Rcode::NXDomain && negative.is_none() {
+			return None;
+		}
+
+		let clamp = |ttl: u32, max: u32| ttl.max(self.config.min_ttl).min(max);
+		let positive = positive.map(|ttl| clamp(ttl, self.config.max_ttl));
+		let negative = negative.map(|ttl| clamp(ttl, self.config.max_negative_ttl));
+
+		let mut message = response.clone();
+		message.set_edns(None);
+		for rr in records_mut(&mut message) {
+			rr.ttl = match (&rr.rdata, negative) {
+				(RData::SOA { .. }, Some(negative)) => negative,
+				_ => clamp(rr.ttl, self.config.max_ttl),
+			};
+		}
+
+		let ttl = match (positive, negative) {
+			(Some(positive), Some(negative)) => positive.min(negative),
+			(Some(ttl), None) | (None, Some(ttl)) => ttl,
+			(None, None) => return None,
+		};
+		if ttl == 0 {
+			return None;
+		}
+		Some((message, ttl))
+	}
+}
+
+fn records_mut(message: &mut Message) -> impl Iterator<Item = &mut ResourceRecord> {
+	message
+		.answers
+		.iter_mut()
+		.chain(message.authorities.iter_mut())
+		.chain(message.additionals.iter_mut())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::edns::Edns;
+	use crate::name::Name;
+	use crate::records::{Class, Type};
+
+	fn question(name: &str) -> Question {
+		Question {
+			name: name.parse().unwrap(),
+			qtype: Type::A,
+			qclass: Class::IN,
+		}
+	}
+
+	fn a(name: &str, ttl: u32) -> ResourceRecord {
+		ResourceRecord {
+			name: name.parse().unwrap(),
+			rtype: Type::A,
+			class: Class::IN,
+			ttl,
+			rdata: RData::A("192.0.2.1".parse().unwrap()),
+		}
+	}
+
+	fn soa(ttl: u32, minimum: u32) -> ResourceRecord {
+		let zone: Name = "example.".parse().unwrap();
+		ResourceRecord {
+			name: zone.clone(),
+			rtype: Type::SOA,
+			class: Class::IN,
+			ttl,
+			rdata: RData::SOA {
+				mname: zone.clone(),
+				rname: zone,
+				serial: 1,
+				refresh: 3600,
+				retry: 600,
+				expire: 86400,
+				minimum,
+			},
+		}
+	}
+
+	fn response(name: &str, answers: Vec<ResourceRecord>) -> Message {
+		Message {
+			questions: vec![question(name)],
+			answers,
+			..Default::default()
+		}
+	}
+
+	fn negative(name: &str, rcode: Rcode, authorities: Vec<ResourceRecord>) -> Message {
+		let mut message = response(name, Vec::new());
+		message.header.set_rcode(rcode);
+		message.authorities = authorities;
+		message
+	}
+
+	fn secs(n: u64) -> Duration {
+		Duration::from_secs(n)
+	}
+
+	fn ttls(hit: &Hit) -> Vec<u32> {
+		hit.message
+			.answers
+			.iter()
+			.chain(&hit.message.authorities)
+			.map(|rr| rr.ttl)
+			.collect()
+	}
+
+	#[test]
+	fn counts_ttls_down_until_expiry() {
+		let cache = Cache::new(CacheConfig::default());
+		let now = Instant::now();
+		cache.insert_at(
+			&response("a.example", vec![a("a.example", 100), a("a.example", 60)]),
+			now,
+		);
+
+		let hit = cache
+			.get_at(&question("A.Example."), now + secs(10))
+			.unwrap();
+		assert_eq!(ttls(&hit), vec![90, 50]);
+		assert!(!hit.stale && !hit.refresh);
+
+		assert!(cache
+			.get_at(&question("a.example"), now + secs(59))
+			.is_some());
+		assert!(cache
+			.get_at(&question("a.example"), now + secs(60))
+			.is_none());
+		assert!(cache.is_empty());
+	}
+
+	#[test]
+	fn clamps_ttls() {
+		let config = CacheConfig {
+			min_ttl: 30,
+			max_ttl: 600,
+			..Default::default()
+		};
+		let cache = Cache::new(config);
+		let now = Instant::now();
+		cache.insert_at(&response("short.example", vec![a("short.example", 5)]), now);
+		cache.insert_at(
+			&response("long.example", vec![a("long.example", 86400)]),
+			now,
+		);
+
+		let hit = cache.get_at(&question("short.example"), now).unwrap();
+		assert_eq!(ttls(&hit), vec![30]);
+		assert!(cache
+			.get_at(&question("short.example"), now + secs(29))
+			.is_some());
+		let hit = cache.get_at(&question("long.example"), now).unwrap();
+		assert_eq!(ttls(&hit), vec![600]);
+		assert!(cache
+			.get_at(&question("long.example"), now + secs(600))
+			.is_none());
+	}
+
+	#[test]
+	fn caches_negative_responses_for_the_soa_minimum() {
+		let cache = Cache::new(CacheConfig::default());
+		let now = Instant::now();
+		cache.insert_at(
+			&negative("nx.example", Rcode::NXDomain, vec![soa(3600, 300)]),
+			now,
+		);
+		cache.insert_at(
+			&negative("nodata.example", Rcode::NoError, vec![soa(120, 300)]),
+			now,
+		);
+
+		let hit = cache
+			.get_at(&question("nx.example"), now + secs(100))
+			.unwrap();
+		assert_eq!(hit.message.rcode(), Rcode::NXDomain);
+		assert_eq!(ttls(&hit), vec![200]);
+		assert!(cache
+			.get_at(&question("nx.example"), now + secs(300))
+			.is_none());
+
+		let hit = cache.get_at(&question("nodata.example"), now).unwrap();
+		assert!(hit.message.answers.is_empty());
+		assert_eq!(ttls(&hit), vec![120]);
+		assert!(cache
+			.get_at(&question("nodata.example"), now + secs(120))
+			.is_none());
+	}
+
+	#[test]
+	fn limits_negative_ttls() {
+		let config = CacheConfig {
+			max_negative_ttl: 60,
+			..Default::default()
+		};
+		let cache = Cache::new(config);
+		let now = Instant::now();
+		cache.insert_at(
+			&negative("nx.example", Rcode::NXDomain, vec![soa(3600, 3600)]),
+			now,
+		);
+		let hit = cache.get_at(&question("nx.example"), now).unwrap();
+		assert_eq!(ttls(&hit), vec![60]);
+	}
+
+	#[test]
+	fn evicts_the_least_recently_used() {
+		let config = CacheConfig {
+			capacity: 2,
+			..Default::default()
+		};
+		let cache = Cache::new(config);
+		let now = Instant::now();
+		cache.insert_at(&response("a.example", vec![a("a.example", 300)]), now);
+		cache.insert_at(&response("b.example", vec![a("b.example", 300)]), now);
+		assert!(cache.get_at(&question("a.example"), now).is_some());
+		cache.insert_at(&response("c.example", vec![a("c.example", 300)]), now);
+
+		assert_eq!(cache.len(), 2);
+		assert!(cache.get_at(&question("b.example"), now).is_none());
+		assert!(cache.get_at(&question("a.example"), now).is_some());
+		assert!(cache.get_at(&question("c.example"), now).is_some());
+	}
+
+	#[test]
+	fn skips_uncacheable_responses() {
+		let cache = Cache::new(CacheConfig::default());
+		let now = Instant::now();
+
+		let servfail = negative("fail.example", Rcode::ServFail, vec![soa(300, 300)]);
+		cache.insert_at(&servfail, now);
+
+		let mut referral = response("www.sub.example", Vec::new());
+		referral.authorities.push(ResourceRecord {
+			name: "sub.example.".parse().unwrap(),
+			rtype: Type::NS,
+			class: Class::IN,
+			ttl: 300,
+			rdata: RData::NS("ns.sub.example.".parse().unwrap()),
+		});
+		cache.insert_at(&referral, now);
+
+		cache.insert_at(&negative("nx.example", Rcode::NXDomain, Vec::new()), now);
+		cache.insert_at(&response("zero.example", vec![a("zero.example", 0)]), now);
+
+		let mut truncated = response("tc.example", vec![a("tc.example", 300)]);
+		truncated.header.set_flags(DnsHeaderFlags::TRUNCATED);
+		cache.insert_at(&truncated, now);
+
+		assert!(cache.is_empty());
+	}
+
+	#[test]
+	fn keeps_the_old_response_when_the_new_one_is_uncacheable() {
+		let cache = Cache::new(CacheConfig::default());
+		let now = Instant::now();
+		cache.insert_at(&response("a.example", vec![a("a.example", 300)]), now);
+		cache.insert_at(&negative("a.example", Rcode::ServFail, Vec::new()), now);
+		let hit = cache.get_at(&question("a.example"), now).unwrap();
+		assert_eq!(hit.message.rcode(), Rcode::NoError);
+	}
+
+	fn serve_stale() -> CacheConfig {
+		CacheConfig {
+			max_stale: 3600,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn serves_stale_responses_while_refreshing() {
+		let cache = Cache::new(serve_stale());
+		let now = Instant::now();
+		cache.insert_at(&response("a.example", vec![a("a.example", 60)]), now);
+
+		let hit = cache
+			.get_at(&question("a.example"), now + secs(60))
+			.unwrap();
+		assert!(hit.stale && hit.refresh);
+		assert_eq!(ttls(&hit), vec![30]);
+
+		// Only one caller is asked to refresh until the recheck time passes.
+		let hit = cache
+			.get_at(&question("a.example"), now + secs(61))
+			.unwrap();
+		assert!(hit.stale && !hit.refresh);
+		let hit = cache
+			.get_at(&question("a.example"), now + secs(90))
+			.unwrap();
+		assert!(hit.stale && hit.refresh);
+
+		// A new response replaces the stale one.
+		cache.insert_at(
+			&response("a.example", vec![a("a.example", 60)]),
+			now + secs(91),
+		);
+		let hit = cache
+			.get_at(&question("a.example"), now + secs(92))
+			.unwrap();
+		assert!(!hit.stale && !hit.refresh);
+		assert_eq!(ttls(&hit), vec![59]);
+	}
+
+	#[test]
+	fn removes_responses_after_max_stale() {
+		let cache = Cache::new(serve_stale());
+		let now = Instant::now();
+		cache.insert_at(&response("a.example", vec![a("a.example", 60)]), now);
+		assert!(cache
+			.get_at(&question("a.example"), now + secs(3659))
+			.is_some());
+		assert!(cache
+			.get_at(&question("a.example"), now + secs(3660))
+			.is_none());
+		assert!(cache.is_empty());
+	}
+
+	#[test]
+	fn serves_stale_negative_responses() {
+		let cache = Cache::new(serve_stale());
+		let now = Instant::now();
+		cache.insert_at(
+			&negative("nx.example", Rcode::NXDomain, vec![soa(60, 60)]),
+			now,
+		);
+		let hit = cache
+			.get_at(&question("nx.example"), now + secs(120))
+			.unwrap();
+		assert!(hit.stale);
+		assert_eq!(hit.message.rcode(), Rcode::NXDomain);
+	}
+
+	#[test]
+	fn prefetches_popular_responses() {
+		let config = CacheConfig {
+			prefetch_hits: 2,
+			prefetch_percent: 10,
+			..Default::default()
+		};
+		let cache = Cache::new(config);
+		let now = Instant::now();
+		cache.insert_at(&response("a.example", vec![a("a.example", 100)]), now);
+
+		// Not popular enough yet.
+		assert!(
+			!cache
+				.get_at(&question("a.example"), now + secs(95))
+				.unwrap()
+				.refresh
+		);
+		// Not close enough to expiring yet.
+		cache.insert_at(&response("a.example", vec![a("a.example", 100)]), now);
+		assert!(
+			!cache
+				.get_at(&question("a.example"), now + secs(10))
+				.unwrap()
+				.refresh
+		);
+		assert!(
+			!cache
+				.get_at(&question("a.example"), now + secs(89))
+				.unwrap()
+				.refresh
+		);
+
+		let hit = cache
+			.get_at(&question("a.example"), now + secs(90))
+			.unwrap();
+		assert!(hit.refresh && !hit.stale);
+		assert!(
+			!cache
+				.get_at(&question("a.example"), now + secs(91))
+				.unwrap()
+				.refresh
+		);
+	}
+
+	#[test]
+	fn refreshes_when_a_prefetch_does_not_arrive_in_time() {
+		let config = CacheConfig {
+			prefetch_hits: 1,
+			prefetch_percent: 50,
+			..serve_stale()
+		};
+		let cache = Cache::new(config);
+		let now = Instant::now();
+		cache.insert_at(&response("a.example", vec![a("a.example", 2)]), now);
+
+		let hit = cache.get_at(&question("a.example"), now + secs(1)).unwrap();
+		assert!(hit.refresh && !hit.stale);
+		let hit = cache.get_at(&question("a.example"), now + secs(2)).unwrap();
+		assert!(hit.refresh && hit.stale);
+		let hit = cache.get_at(&question("a.example"), now + secs(3)).unwrap();
+		assert!(!hit.refresh && hit.stale);
+	}
+
+	#[test]
+	fn drops_the_opt_record() {
+		let cache = Cache::new(CacheConfig::default());
+		let now = Instant::now();
+		let mut message = response("a.example", vec![a("a.example", 300)]);
+		message.set_edns(Some(Edns::new(1232)));
+		cache.insert_at(&message, now);
+		let hit = cache.get_at(&question("a.example"), now).unwrap();
+		assert!(hit.message.edns().is_none());
+	}
+}
@@ -0,0 +1,266 @@
+//! Command line arguments, loosely modelled on `dig`
+
+use std::net::{IpAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use dns::client::{ResolverConfig, PORT};
+use dns::edns::EdnsOption;
+use dns::records::{Class, Type};
+use dns::resolver::iterative::NameServer;
+use dns::resolver::IterativeConfig;
+use dns::Name;
+
+pub const USAGE: &str = "\
+Usage: dns [@server] [name] [type] [class] [options]
+
+The server defaults to the first nameserver in /etc/resolv.conf. With --trace
+it is used instead of the root servers.
+
+Options:
+  -p, --port <port>       Port to send the query to (default 53)
+  --timeout <seconds>     How long to wait for each reply (default from resolv.conf)
+  --retries <n>           How many times to resend an unanswered query (default from resolv.conf)
+  --recurse               Ask the server to resolve the name recursively (default)
+  --no-recurse            Only ask for what the server knows itself
+  --tcp                   Use TCP instead of UDP
+  --trace                 Follow referrals down from the root, showing each response
+  --bufsize <bytes>       UDP payload size to advertise with EDNS (default 1232)
+  --no-edns               Don't send an EDNS OPT record
+  --nsid                  Ask the server to identify itself
+  --subnet <addr/prefix>  Send an EDNS client subnet
+  --format <format>       How to show the response: text, like dig, json or hex (default text)
+  -h, --help              Show this message";
+
+/// How to show the response.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+	/// Sections of records in zone file format, like `dig`.
+	Text,
+	/// The response as JSON.
+	/// https://tools.ietf.org/html/rfc8427
+	Json,
+	/// Hexdumps of the request and response.
+	Hex,
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+	pub server: Option<String>,
+	pub port: Option<u16>,
+	pub name: Name,
+	pub qtype: Type,
+	pub qclass: Class,
+	pub timeout: Option<Duration>,
+	pub retries: Option<u32>,
+	pub recurse: bool,
+	pub tcp: bool,
+	pub trace: bool,
+	pub edns: bool,
+	pub bufsize: Option<u16>,
+	pub nsid: bool,
+	pub subnet: Option<(IpAddr, u8)>,
+	pub format: Format,
+}
+
+impl Default for Options {
+	fn default() -> Options {
+		Options {
+			server: None,
+			port: None,
+			name: "www.google.com".parse().unwrap(),
+			qtype: Type::A,
+			qclass: Class::IN,
+			timeout: None,
+			retries: None,
+			recurse: true,
+			tcp: false,
+			trace: false,
+			edns: true,
+			bufsize: None,
+			nsid: false,
+			subnet: None,
+			format: Format::Text,
+		}
+	}
+}
+
+impl Options {
+	/// Override the parts of `config` given on the command line.
+	pub fn apply_to(&self, config: &mut ResolverConfig) -> dns::Result<()> {
+		if let Some(server) = &self.server {
+			config.nameservers = (server.as_str(), PORT).to_socket_addrs()?.collect();
+		}
+		if let Some(port) = self.port {
+			for addr in &mut config.nameservers {
+				addr.set_port(port);
+			}
+		}
+		if let Some(timeout) = self.timeout {
+			config.timeout = timeout;
+		}
+		if let Some(retries) = self.retries {
+			config.attempts = retries.saturating_add(1);
+		}
+		if self.tcp {
+			config.use_tcp = true;
+		}
+		if let Some(bufsize) = self.bufsize {
+			config.edns_udp_size = Some(bufsize);
+		}
+		if !self.edns {
+			config.edns_udp_size = None;
+		}
+		Ok(())
+	}
+
+	/// The settings for `--trace`, taken from `config` once the command line
+	/// has been applied to it.
+	pub fn iterative_config(&self, config: &ResolverConfig) -> IterativeConfig {
+		let mut iterative = IterativeConfig {
+			timeout: config.timeout,
+			attempts: config.attempts,
+			edns_udp_size: config.edns_udp_size,
+			..Default::default()
+		};
+		if let Some(server) = &self.server {
+			iterative.root_hints = vec![NameServer {
+				name: server.parse().unwrap_or_else(|_| Name::root()),
+				addrs: config.nameservers.iter().map(|addr| addr.ip()).collect(),
+			}];
+		}
+		if let Some(port) = self.port {
+			iterative.port = port;
+		}
+		iterative
+	}
+
+	/// The EDNS options to send with the query.
+	pub fn edns_options(&self) -> Vec<EdnsOption> {
+		let mut options = Vec::new();
+		if self.nsid {
+			options.push(EdnsOption::Nsid(Vec::new()));
+		}
+		if let Some((address, source_prefix)) = self.subnet {
+			options.push(EdnsOption::ClientSubnet {
+				source_prefix,
+				scope_prefix: 0,
+				address,
+			});
+		}
+		options
+	}
+}
+
+/// What the user asked us to do.
+pub enum Command {
+	Query(Options),
+	Help,
+}
+
+/// Parse the arguments, not including the program name.
+///
+/// Like `dig`, the type and class can come in either order after the name.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+	let mut options = Options::default();
+	let mut name = None;
+	let mut qtype = None;
+	let mut qclass = None;
+
+	let mut args = args.into_iter();
+	while let Some(arg) = args.next() {
+		let mut value = |option: &str| {
+			args.next()
+				.ok_or_else(|| format!("{} needs a value", option))
+		};
+		match arg.as_str() {
+			"-h" | "--help" => return Ok(Command::Help),
+			"-p" | "--port" => {
+				let port = value(&arg)?;
+				options.port = Some(
+					port.parse()
+						.map_err(|_| format!("invalid port {:?}", port))?,
+				);
+			}
+			"--timeout" => {
+				let secs = value(&arg)?;
+				let timeout = secs
+					.parse()
+					.ok()
+					.filter(|&s: &f64| s > 0.0)
+					.and_then(|s| Duration::try_from_secs_f64(s).ok())
+					.ok_or_else(|| format!("invalid timeout {:?}", secs))?;
+				options.timeout = Some(timeout);
+			}
+			"--retries" => {
+				let retries = value(&arg)?;
+				options.retries = Some(
+					retries
+						.parse()
+						.map_err(|_| format!("invalid retry count {:?}", retries))?,
+				);
+			}
+			"--recurse" => options.recurse = true,
+			"--no-recurse" => options.recurse = false,
+			"--tcp" => options.tcp = true,
+			"--trace" => options.trace = true,
+			"--bufsize" => {
+				let size = value(&arg)?;
+				options.bufsize = Some(
+					size.parse()
+						.map_err(|_| format!("invalid buffer size {:?}", size))?,
+				);
+			}
+			"--no-edns" => options.edns = false,
+			"--nsid" => options.nsid = true,
+			"--subnet" => {
+				let subnet = value(&arg)?;
+				options.subnet = Some(
+					parse_subnet(&subnet).ok_or_else(|| format!("invalid subnet {:?}", subnet))?,
+				);
+			}
+			"--format" => {
+				options.format = match value(&arg)?.as_str() {
+					"text" => Format::Text,
+					"json" => Format::Json,
+					"hex" => Format::Hex,
+					other => return Err(format!("unknown format {:?}", other)),
+				}
+			}
+			_ if arg.starts_with('-') && arg.len() > 1 => {
+				return Err(format!("unknown option {}", arg))
+			}
+			_ if arg.starts_with('@') => options.server = Some(arg[1..].to_string()),
+			_ if name.is_none() => name = Some(arg),
+			_ => {
+				if let (None, Ok(t)) = (qtype, arg.parse()) {
+					qtype = Some(t);
+				} else if let (None, Ok(c)) = (qclass, arg.parse()) {
+					qclass = Some(c);
+				} else {
+					return Err(format!("unexpected argument {:?}", arg));
+				}
+			}
+		}
+	}
+
+	if let Some(name) = name {
+		options.name = name
+			.parse()
+			.map_err(|e| format!("invalid name {:?}: {}", name, e))?;
+	}
+	options.qtype = qtype.unwrap_or(options.qtype);
+	options.qclass = qclass.unwrap_or(options.qclass);
+	Ok(Command::Query(options))
+}
+
+/// Parse `address/prefix`, or just `address` for a whole address.
+fn parse_subnet(s: &str) -> Option<(IpAddr, u8)> {
+	let mut parts = s.splitn(2, '/');
+	let address: IpAddr = parts.next()?.parse().ok()?;
+	let max = if address.is_ipv4() { 32 } else { 128 };
+	let prefix = match parts.next() {
+		Some(prefix) => prefix.parse().ok().filter(|&p| p <= max)?,
+		None => max,
+	};
+	Some((address, prefix))
+}
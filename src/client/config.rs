@@ -0,0 +1,238 @@
+//! Resolver configuration, normally read from `/etc/resolv.conf`
+//! https://man7.org/linux/man-pages/man5/resolv.conf.5.html
+
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV6};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::edns;
+use crate::error::Result;
+use crate::name::Name;
+
+/// The standard DNS port.
+pub const PORT: u16 = 53;
+
+/// Where the system resolver configuration lives.
+pub const RESOLV_CONF_PATH: &str = "/etc/resolv.conf";
+
+/// Limits on the values in resolv.conf, matching glibc.
+const MAX_NAMESERVERS: usize = 3;
+const MAX_NDOTS: u32 = 15;
+const MAX_TIMEOUT: u64 = 30;
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Which servers to ask, and how.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolverConfig {
+	/// Servers to send queries to, in order of preference.
+	pub nameservers: Vec<SocketAddr>,
+	/// Domains to try appending to names that aren't fully qualified.
+	pub search: Vec<Name>,
+	/// Names with at least this many dots are tried as they are before the
+	/// search list is used.
+	pub ndots: u32,
+	/// How long to wait for a reply from a server before trying the next one.
+	pub timeout: Duration,
+	/// How many times to try each server.
+	pub attempts: u32,
+	/// What to multiply the timeout by after each round of attempts.
+	pub backoff: u32,
+	/// The timeout never starts or grows past this.
+	pub max_timeout: Duration,
+	/// Spread queries across the servers instead of always starting with the
+	/// first.
+	pub rotate: bool,
+	/// Always use TCP, not just when a UDP response is truncated.
+	pub use_tcp: bool,
+	/// The UDP payload size to advertise with EDNS, or `None` to not use EDNS.
+	pub edns_udp_size: Option<u16>,
+}
+
+/// The same defaults glibc uses when resolv.conf is missing or silent.
+impl Default for ResolverConfig {
+	fn default() -> ResolverConfig {
+		ResolverConfig {
+			nameservers: vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), PORT)],
+			search: Vec::new(),
+			ndots: 1,
+			timeout: Duration::from_secs(5),
+			attempts: 2,
+			backoff: 2,
+			max_timeout: Duration::from_secs(MAX_TIMEOUT),
+			rotate: false,
+			use_tcp: false,
+			edns_udp_size: Some(edns::DEFAULT_UDP_PAYLOAD_SIZE),
+		}
+	}
+}
+
+impl ResolverConfig {
+	/// Read the system configuration from `/etc/resolv.conf`. If it doesn't
+	/// exist, the defaults are used.
+	pub fn from_system() -> Result<ResolverConfig> {
+		match fs::read_to_string(RESOLV_CONF_PATH) {
+			Ok(s) => Ok(ResolverConfig::parse(&s)),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Default::default()),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	/// Read a configuration file in resolv.conf format.
+	pub fn from_path<P: AsRef<Path>>(path: P) -> Result<ResolverConfig> {
+		Ok(ResolverConfig::parse(&fs::read_to_string(path)?))
+	}
+
+	/// Parse the contents of a resolv.conf file. Like the C library, lines that
+	/// can't be understood are ignored rather than treated as errors.
+	pub fn parse(s: &str) -> ResolverConfig {
+		let mut config = ResolverConfig::default();
+		let mut nameservers = Vec::new();
+
+		for line in s.lines() {
+			if line.starts_with(';') || line.starts_with('#') {
+				continue;
+			}
+			let mut words = line.split_whitespace();
+			match words.next() {
+				Some("nameserver") => {
+					if let Some(addr) = words.next().and_then(parse_nameserver) {
+						if nameservers.len() < MAX_NAMESERVERS {
+							nameservers.push(addr);
+						}
+					}
+				}
+				// domain and search both set the search list, and whichever
+				// comes last wins.
+				Some("domain") => {
+					if let Some(domain) = words.next() {
+						config.search = domain.parse().into_iter().collect();
+					}
+				}
+				Some("search") => {
+					config.search = words.filter_map(|d| d.parse().ok()).collect();
+				}
+				Some("options") => {
+					for option in words {
+						config.apply_option(option);
+					}
+				}
+				_ => {}
+			}
+		}
+
+		if !nameservers.is_empty() {
+			config.nameservers = nameservers;
+		}
+		config
+	}
+
+	fn apply_option(&mut self, option: &str) {
+		let mut parts = option.splitn(2, ':');
+		let name = parts.next().unwrap_or("");
+		let value = parts.next().and_then(|v| v.parse::<u32>().ok());
+		match (name, value) {
+			("ndots", Some(n)) => self.ndots = n.min(MAX_NDOTS),
+			("timeout", Some(n)) => {
+				self.timeout = Duration::from_secs((n as u64).clamp(1, MAX_TIMEOUT))
+			}
+			("attempts", Some(n)) => self.attempts = n.clamp(1, MAX_ATTEMPTS),
+			("rotate", None) => self.rotate = true,
+			("use-vc", None) => self.use_tcp = true,
+			_ => {}
+		}
+	}
+}
+
+/// Nameservers are IP addresses, and IPv6 ones may have a `%scope` suffix.
+/// Numeric scopes are kept, but we have no way to look up interface names so
+/// those are dropped.
+fn parse_nameserver(s: &str) -> Option<SocketAddr> {
+	let mut parts = s.splitn(2, '%');
+	let ip: IpAddr = parts.next()?.parse().ok()?;
+	match (ip, parts.next().and_then(|scope| scope.parse().ok())) {
+		(IpAddr::V6(ip), Some(scope_id)) => {
+			Some(SocketAddr::V6(SocketAddrV6::new(ip, PORT, 0, scope_id)))
+		}
+		_ => Some(SocketAddr::new(ip, PORT)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn clamps_options() {
+		let config = ResolverConfig::parse("options timeout:0 attempts:0 ndots:20\n");
+		assert_eq!(config.timeout, Duration::from_secs(1));
+		assert_eq!(config.attempts, 1);
+		assert_eq!(config.ndots, MAX_NDOTS);
+
+		let config = ResolverConfig::parse("options timeout:1000 attempts:1000\n");
+		assert_eq!(config.timeout, Duration::from_secs(MAX_TIMEOUT));
+		assert_eq!(config.attempts, MAX_ATTEMPTS);
+	}
+
+	#[test]
+	fn reads_nameservers() {
+		let config = ResolverConfig::parse(
+			"nameserver 192.0.2.1\n\
+			 nameserver fe80::1%2\n\
+			 nameserver fe80::2%eth0\n\
+			 nameserver 192.0.2.4\n",
+		);
+		assert_eq!(
+			config.nameservers,
+			vec![
+				"192.0.2.1:53".parse().unwrap(),
+				"[fe80::1%2]:53".parse().unwrap(),
+				"[fe80::2]:53".parse().unwrap(),
+			]
+		);
+
+		let config = ResolverConfig::parse("nameserver not-an-address\n");
+		assert_eq!(config.nameservers, ResolverConfig::default().nameservers);
+	}
+
+	#[test]
+	fn last_search_or_domain_wins() {
+		let name = |s: &str| s.parse::<Name>().unwrap();
+		let config = ResolverConfig::parse("domain a.example\nsearch b.example c.example\n");
+		assert_eq!(config.search, vec![name("b.example"), name("c.example")]);
+		let config = ResolverConfig::parse("search b.example c.example\ndomain a.example\n");
+		assert_eq!(config.search, vec![name("a.example")]);
+	}
+
+	#[test]
+	fn reads_flags_and_ignores_the_rest() {
+		let config = ResolverConfig::parse(
+			"# nameserver 192.0.2.1\n\
+			 ; options rotate\n\
+			 sortlist 192.0.2.0/24\n\
+			 options rotate use-vc ndots:x no-such-option\n",
+		);
+		assert_eq!(
+			config,
+			ResolverConfig {
+				rotate: true,
+				use_tcp: true,
+				..Default::default()
+			}
+		);
+	}
+
+	#[test]
+	fn reads_a_file() {
+		let path = std::env::temp_dir().join(format!("resolv.conf.{}", std::process::id()));
+		fs::write(&path, "nameserver 192.0.2.1\noptions ndots:3\n").unwrap();
+		let config = ResolverConfig::from_path(&path);
+		fs::remove_file(&path).unwrap();
+		let config = config.unwrap();
+		assert_eq!(config.nameservers, vec!["192.0.2.1:53".parse().unwrap()]);
+		assert_eq!(config.ndots, 3);
+
+		assert!(ResolverConfig::from_path(&path).is_err());
+	}
+}
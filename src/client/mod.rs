@@ -0,0 +1,596 @@
+//! Sending queries to name servers and collecting their responses
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rand::rngs::OsRng;
+use rand::Rng;
+
+use crate::edns::Edns;
+use crate::error::{Error, Result};
+use crate::wire::{DnsHeaderFlags, Message, Rcode};
+
+pub mod config;
+
+pub use config::{ResolverConfig, PORT};
+
+/// The largest UDP payload size we'll advertise, and the smallest buffer we
+/// receive into.
+const MAX_UDP_SIZE: usize = 4096;
+
+/// The largest message that fits after the two byte length prefix.
+const MAX_TCP_SIZE: usize = 0xffff;
+
+/// How a response reached us.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Protocol {
+	Udp,
+	Tcp,
+}
+
+impl fmt::Display for Protocol {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Protocol::Udp => write!(f, "UDP"),
+			Protocol::Tcp => write!(f, "TCP"),
+		}
+	}
+}
+
+/// A response to a query, along with the bytes exchanged to get it.
+#[derive(Clone, Debug)]
+pub struct Response {
+	/// The server that answered.
+	pub server: SocketAddr,
+	pub protocol: Protocol,
+	/// The request exactly as it was sent.
+	pub request: Vec<u8>,
+	/// The response exactly as it was received.
+	pub data: Vec<u8>,
+	pub message: Message,
+	/// How long the server took to answer the attempt that succeeded.
+	pub time: Duration,
+}
+
+/// Sends queries to the servers in a `ResolverConfig`.
+#[derive(Debug)]
+pub struct Client {
+	config: ResolverConfig,
+	/// How many queries have been sent, used to pick the first server to try
+	/// when the config asks for rotation.
+	queries: AtomicUsize,
+}
+
+impl Clone for Client {
+	fn clone(&self) -> Client {
+		Client {
+			config: self.config.clone(),
+			queries: AtomicUsize::new(self.queries.load(Ordering::Relaxed)),
+		}
+	}
+}
+
+impl Client {
+	pub fn new(config: ResolverConfig) -> Client {
+		Client {
+			config,
+			queries: AtomicUsize::new(0),
+		}
+	}
+
+	/// A client using the system configuration.
+	pub fn from_system() -> Result<Client> {
+		Ok(Client::new(ResolverConfig::from_system()?))
+	}
+
+	pub fn config(&self) -> &ResolverConfig {
+		&self.config
+	}
+
+	/// Send a request and wait for the response.
+	///
+	/// Unless the request already has an OPT record, one is added advertising
+	/// the configured UDP payload size. If the server doesn't understand it
+	/// the request is sent again without.
+	/// https://tools.ietf.org/html/rfc6891#section-7
+	///
+	/// Each attempt goes through every configured server in turn, waiting
+	/// for the timeout before moving on to the next. Truncated UDP responses
+	/// are retried over TCP. The timeout grows by the
+	/// backoff factor after each attempt. If nobody answers the error is
+	/// `Error::Timeout`.
+	///
+	/// The request is given a fresh random ID, and anything over UDP that
+	/// doesn't match it or its question is ignored, so a forged response has
+	/// to guess both the ID and our source port.
+	/// https://tools.ietf.org/html/rfc5452#section-9.1
+	pub fn query(&self, request: &Message) -> Result<Response> {
+		let mut request = request.clone();
+		if request.edns().is_none() {
+			if let Some(size) = self.config.edns_udp_size {
+				request.set_edns(Some(Edns::new(size.min(MAX_UDP_SIZE as u16))));
+			}
+		}
+
+		let response = self.send(&request)?;
+		let rcode = response.message.header.rcode();
+		if request.edns().is_some()
+			&& response.message.edns().is_none()
+			&& (rcode == Rcode::FormErr || rcode == Rcode::NotImp)
+		{
+			request.set_edns(None);
+			return self.send(&request);
+		}
+		Ok(response)
+	}
+
+	fn send(&self, request: &Message) -> Result<Response> {
+		let servers = self.server_order();
+		if servers.is_empty() {
+			return Err(Error::NoNameservers);
+		}
+
+		let mut request = request.clone();
+		request.header.id = OsRng.gen();
+		// Whatever size we advertised, we're ready for at least as much.
+		let buf_size = request.edns().map_or(MAX_UDP_SIZE, |edns| {
+			(edns.udp_payload_size as usize).max(MAX_UDP_SIZE)
+		});
+		let data = request.encode()?;
+
+		let mut sockets = Sockets::default();
+		let mut timeout = self.config.timeout.min(self.config.max_timeout);
+		// A server we can't talk to at all is skipped. If none of the others
+		// answer either it's a timeout, unless we never got as far as waiting
+		// for any of them, in which case we report why.
+		let mut last_error = None;
+		let mut waited = false;
+		for _ in 0..self.config.attempts {
+			for server in &servers {
+				let start = Instant::now();
+				match self.attempt(&mut sockets, server, &data, &request, buf_size, timeout) {
+					Ok(Some((response, message, protocol))) => {
+						return Ok(Response {
+							server: *server,
+							protocol,
+							request: data,
+							data: response,
+							message,
+							time: start.elapsed(),
+						})
+					}
+					Ok(None) => waited = true,
+					Err(e) => last_error = Some(e),
+				}
+			}
+			if timeout < self.config.max_timeout {
+				timeout = (timeout * self.config.backoff).min(self.config.max_timeout);
+			}
+		}
+
+		match last_error {
+			Some(e) if !waited => Err(e),
+			_ => Err(Error::Timeout {
+				servers,
+				attempts: self.config.attempts,
+			}),
+		}
+	}
+
+	/// Make one attempt at getting a response from `server`. This is over UDP
+	/// unless the config asks for TCP, or the UDP response was truncated.
+	/// https://tools.ietf.org/html/rfc7766#section-5
+	fn attempt(
+		&self,
+		sockets: &mut Sockets,
+		server: &SocketAddr,
+		data: &[u8],
+		request: &Message,
+		buf_size: usize,
+		timeout: Duration,
+	) -> Result<Option<(Vec<u8>, Message, Protocol)>> {
+		if !self.config.use_tcp {
+			let socket = sockets.get(server)?;
+			socket.send_to(data, server)?;
+			match recv_response(socket, server, request, buf_size, timeout)? {
+				Some((response, message))
+					if !message.header.flags().contains(DnsHeaderFlags::TRUNCATED) =>
+				{
+					return Ok(Some((response, message, Protocol::Udp)))
+				}
+				Some(_) => {}
+				None => return Ok(None),
+			}
+		}
+
+		Ok(query_tcp(server, data, request, timeout)?
+			.map(|(response, message)| (response, message, Protocol::Tcp)))
+	}
+
+	/// The servers to try for the next query, in order.
+	fn server_order(&self) -> Vec<SocketAddr> {
+		let mut servers = self.config.nameservers.clone();
+		if self.config.rotate && !servers.is_empty() {
+			let start = self.queries.fetch_add(1, Ordering::Relaxed) % servers.len();
+			servers.rotate_left(start);
+		}
+		servers
+	}
+}
+
+/// Send a request over a new TCP connection, and wait up to `timeout` for each
+/// read of the response. Returns `None` if the timeout expires.
+///
+/// Nobody else can inject messages into the connection, so unlike over UDP a
+/// response that doesn't match the request is an error.
+/// https://tools.ietf.org/html/rfc1035#section-4.2.2
+fn query_tcp(
+	server: &SocketAddr,
+	data: &[u8],
+	request: &Message,
+	timeout: Duration,
+) -> Result<Option<(Vec<u8>, Message)>> {
+	if data.len() > MAX_TCP_SIZE {
+		return Err(Error::MessageTooLong);
+	}
+
+	let result = (|| -> io::Result<Vec<u8>> {
+		let mut stream = TcpStream::connect_timeout(server, timeout)?;
+		stream.set_read_timeout(Some(timeout))?;
+		stream.set_write_timeout(Some(timeout))?;
+
+		// Messages are prefixed with their length. Sending it in the same
+		// segment as the message avoids a round trip with some servers.
+		let mut framed = Vec::with_capacity(data.len() + 2);
+		framed.extend_from_slice(&(data.len() as u16).to_be_bytes());
+		framed.extend_from_slice(data);
+		stream.write_all(&framed)?;
+
+		let mut length = [0; 2];
+		stream.read_exact(&mut length)?;
+		let mut response = vec![0; u16::from_be_bytes(length) as usize];
+		stream.read_exact(&mut response)?;
+		Ok(response)
+	})();
+
+	match result {
+		Ok(response) => {
+			let message = decode_response(request, &response)?;
+			Ok(Some((response, message)))
+		}
+		Err(e) if is_timeout(&e) => Ok(None),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Which of these we get from a socket with a timeout depends on the platform.
+fn is_timeout(e: &io::Error) -> bool {
+	e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+}
+
+/// A socket for each address family, bound when first needed.
+#[derive(Default)]
+struct Sockets {
+	v4: Option<UdpSocket>,
+	v6: Option<UdpSocket>,
+}
+
+impl Sockets {
+	fn get(&mut self, server: &SocketAddr) -> io::Result<&UdpSocket> {
+		let socket = match server {
+			SocketAddr::V4(_) => &mut self.v4,
+			SocketAddr::V6(_) => &mut self.v6,
+		};
+		if socket.is_none() {
+			*socket = Some(bind_ephemeral(server)?);
+		}
+		Ok(socket.as_ref().unwrap())
+	}
+}
+
+/// Wait up to `timeout` for a response to `request` from `server`, ignoring
+/// anything else that arrives. Returns `None` if the timeout expires.
+fn recv_response(
+	socket: &UdpSocket,
+	server: &SocketAddr,
+	request: &Message,
+	buf_size: usize,
+	timeout: Duration,
+) -> Result<Option<(Vec<u8>, Message)>> {
+	let deadline = Instant::now() + timeout;
+	let mut buf = vec![0; buf_size];
+	loop {
+		let now = Instant::now();
+		if now >= deadline {
+			return Ok(None);
+		}
+		socket.set_read_timeout(Some(deadline - now))?;
+
+		let (length, from) = match socket.recv_from(&mut buf) {
+			Ok(received) => received,
+			Err(e) if is_timeout(&e) => return Ok(None),
+			Err(e) => return Err(e.into()),
+		};
+
+		// Anyone can send us a datagram, and only the server we asked should
+		// be believed.
+		if from.ip() != server.ip() || from.port() != server.port() {
+			continue;
+		}
+		let data = &buf[..length];
+		if let Ok(message) = decode_response(request, data) {
+			return Ok(Some((data.to_vec(), message)));
+		}
+	}
+}
+
+/// Decode `data`, checking that it is a response to `request`.
+fn decode_response(request: &Message, data: &[u8]) -> Result<Message> {
+	let message = Message::decode(data)?;
+	if message.header.id != request.header.id {
+		return Err(Error::IdMismatch {
+			expected: request.header.id,
+			received: message.header.id,
+		});
+	}
+	// A server that couldn't make sense of the request may not repeat the
+	// question, and we need to see its error to retry without EDNS.
+	// https://tools.ietf.org/html/rfc6891#section-7
+	let rcode = message.header.rcode();
+	let unparsed =
+		message.questions.is_empty() && (rcode == Rcode::FormErr || rcode == Rcode::NotImp);
+	let matches = message.header.flags().contains(DnsHeaderFlags::RESPONSE)
+		&& (unparsed
+			|| message.questions.len() == request.questions.len()
+				&& message
+					.questions
+					.iter()
+					.zip(&request.questions)
+					.all(|(a, b)| a == b));
+	if !matches {
+		return Err(Error::QuestionMismatch);
+	}
+	Ok(message)
+}
+
+/// Bind a socket of the right family to talk to `server`. The port is left to
+/// the OS, which picks an unused ephemeral port and on most systems randomises
+/// it, so that two queries don't collide and spoofed responses are harder to
+/// aim.
+/// https://tools.ietf.org/html/rfc5452#section-4.5
+fn bind_ephemeral(server: &SocketAddr) -> io::Result<UdpSocket> {
+	let local: SocketAddr = match server {
+		SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+		SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+	};
+	UdpSocket::bind(local)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::name::Name;
+	use crate::records::{Class, RData, Type};
+	use crate::wire::{Question, ResourceRecord};
+	use std::net::TcpListener;
+	use std::sync::atomic::AtomicUsize;
+	use std::sync::Arc;
+	use std::thread;
+
+	fn request() -> Message {
+		Message {
+			questions: vec![Question {
+				name: Name::root(),
+				qtype: Type::NS,
+				qclass: Class::IN,
+			}],
+			..Default::default()
+		}
+	}
+
+	fn client(nameservers: Vec<SocketAddr>) -> Client {
+		Client::new(ResolverConfig {
+			nameservers,
+			timeout: Duration::from_millis(100),
+			attempts: 1,
+			..Default::default()
+		})
+	}
+
+	/// Answer UDP requests on `socket` with whatever `replies` makes for each
+	/// request and the address it came from, in order.
+	fn serve_udp<F>(socket: UdpSocket, replies: F)
+	where
+		F: Fn(&Message, SocketAddr) -> Vec<Message> + Send + 'static,
+	{
+		thread::spawn(move || {
+			let mut buf = [0; 4096];
+			while let Ok((len, from)) = socket.recv_from(&mut buf) {
+				let request = Message::decode(&buf[..len]).unwrap();
+				for reply in replies(&request, from) {
+					socket.send_to(&reply.encode().unwrap(), from).unwrap();
+				}
+			}
+		});
+	}
+
+	/// Answer TCP connections on `listener` with a full answer.
+	fn serve_tcp(listener: TcpListener) {
+		thread::spawn(move || {
+			for mut stream in listener.incoming().flatten() {
+				let mut length = [0; 2];
+				stream.read_exact(&mut length).unwrap();
+				let mut data = vec![0; u16::from_be_bytes(length) as usize];
+				stream.read_exact(&mut data).unwrap();
+				let request = Message::decode(&data).unwrap();
+				let response = answer(&request, "valid.example").encode().unwrap();
+				stream
+					.write_all(&(response.len() as u16).to_be_bytes())
+					.unwrap();
+				stream.write_all(&response).unwrap();
+			}
+		});
+	}
+
+	/// A server that truncates every UDP response and answers in full over
+	/// TCP on the same port. Returns its address and how many UDP requests it
+	/// has had.
+	fn truncating_server() -> (SocketAddr, Arc<AtomicUsize>) {
+		let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let addr = server.local_addr().unwrap();
+		serve_tcp(TcpListener::bind(addr).unwrap());
+		let udp_requests = Arc::new(AtomicUsize::new(0));
+		let counter = udp_requests.clone();
+		serve_udp(server, move |request, _| {
+			counter.fetch_add(1, Ordering::SeqCst);
+			let mut truncated = answer(request, "valid.example");
+			truncated.answers.clear();
+			truncated
+				.header
+				.set_flags(DnsHeaderFlags::RESPONSE | DnsHeaderFlags::TRUNCATED);
+			vec![truncated]
+		});
+		(addr, udp_requests)
+	}
+
+	/// A response to `request` naming `ns` as a root server.
+	fn answer(request: &Message, ns: &str) -> Message {
+		let mut response = Message {
+			questions: request.questions.clone(),
+			answers: vec![ResourceRecord {
+				name: Name::root(),
+				rtype: Type::NS,
+				class: Class::IN,
+				ttl: 300,
+				rdata: RData::NS(ns.parse().unwrap()),
+			}],
+			..Default::default()
+		};
+		response.header.id = request.header.id;
+		response.header.set_flags(DnsHeaderFlags::RESPONSE);
+		response
+	}
+
+	fn answered_by(response: &Response) -> String {
+		response.message.answers[0].rdata.to_string()
+	}
+
+	#[test]
+	fn ignores_responses_from_elsewhere() {
+		let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let addr = server.local_addr().unwrap();
+		// The right port on the wrong address, and the wrong port on the right
+		// address.
+		let forgers = vec![
+			UdpSocket::bind(("127.0.0.2", addr.port())).unwrap(),
+			UdpSocket::bind("127.0.0.1:0").unwrap(),
+		];
+		serve_udp(server, move |request, from| {
+			let forged = answer(request, "forged.example").encode().unwrap();
+			for forger in &forgers {
+				forger.send_to(&forged, from).unwrap();
+			}
+			vec![answer(request, "valid.example")]
+		});
+		let response = client(vec![addr]).query(&request()).unwrap();
+		assert_eq!(answered_by(&response), "valid.example.");
+	}
+
+	#[test]
+	fn ignores_responses_to_other_requests() {
+		let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let addr = server.local_addr().unwrap();
+		serve_udp(server, |request, _| {
+			let mut wrong_id = answer(request, "forged.example");
+			wrong_id.header.id ^= 1;
+			let mut not_response = answer(request, "forged.example");
+			not_response.header.set_flags(DnsHeaderFlags::empty());
+			let mut wrong_question = answer(request, "forged.example");
+			wrong_question.questions[0].qtype = Type::A;
+			vec![
+				wrong_id,
+				not_response,
+				wrong_question,
+				answer(request, "valid.example"),
+			]
+		});
+		let response = client(vec![addr]).query(&request()).unwrap();
+		assert_eq!(answered_by(&response), "valid.example.");
+	}
+
+	#[test]
+	fn retries_truncated_responses_over_tcp() {
+		let (addr, udp_requests) = truncating_server();
+		let response = client(vec![addr]).query(&request()).unwrap();
+		assert_eq!(response.protocol, Protocol::Tcp);
+		assert_eq!(answered_by(&response), "valid.example.");
+		assert_eq!(udp_requests.load(Ordering::SeqCst), 1);
+	}
+
+	#[test]
+	fn uses_only_tcp_when_asked() {
+		let (addr, udp_requests) = truncating_server();
+		let client = Client::new(ResolverConfig {
+			nameservers: vec![addr],
+			use_tcp: true,
+			..Default::default()
+		});
+		let response = client.query(&request()).unwrap();
+		assert_eq!(response.protocol, Protocol::Tcp);
+		assert_eq!(answered_by(&response), "valid.example.");
+		assert_eq!(udp_requests.load(Ordering::SeqCst), 0);
+	}
+
+	#[test]
+	fn retries_without_edns_after_formerr() {
+		let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let addr = server.local_addr().unwrap();
+		serve_udp(server, |request, _| {
+			if request.edns().is_none() {
+				return vec![answer(request, "valid.example")];
+			}
+			let mut error = Message::default();
+			error.header.id = request.header.id;
+			error.header.set_flags(DnsHeaderFlags::RESPONSE);
+			error.header.set_rcode(Rcode::FormErr);
+			vec![error]
+		});
+		let response = client(vec![addr]).query(&request()).unwrap();
+		assert_eq!(answered_by(&response), "valid.example.");
+		assert!(Message::decode(&response.request).unwrap().edns().is_none());
+	}
+
+	#[test]
+	fn times_out_when_any_server_was_waited_on() {
+		// Sending to the broadcast address fails without SO_BROADCAST.
+		let unreachable = "255.255.255.255:53".parse().unwrap();
+		let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let client = client(vec![unreachable, silent.local_addr().unwrap()]);
+		let error = client.query(&request()).unwrap_err();
+		assert!(error.is_timeout(), "{:?}", error);
+	}
+
+	#[test]
+	fn timeout_is_capped() {
+		let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let client = Client::new(ResolverConfig {
+			nameservers: vec![silent.local_addr().unwrap()],
+			timeout: Duration::MAX,
+			max_timeout: Duration::from_millis(100),
+			attempts: 1,
+			..Default::default()
+		});
+		let error = client.query(&request()).unwrap_err();
+		assert!(error.is_timeout(), "{:?}", error);
+	}
+
+	#[test]
+	fn reports_why_no_server_could_be_waited_on() {
+		let unreachable = "255.255.255.255:53".parse().unwrap();
+		let client = client(vec![unreachable]);
+		assert!(matches!(client.query(&request()), Err(Error::Io(_))));
+	}
+}
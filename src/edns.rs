@@ -0,0 +1,300 @@
+//! Extension mechanisms for DNS, carried in an OPT pseudo-record
+//! https://tools.ietf.org/html/rfc6891
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::error::{Error, Result};
+use crate::name::Name;
+use crate::records::{Class, Hex, RData, Type};
+use crate::wire::{Reader, ResourceRecord, Writer};
+
+/// The only version of EDNS there is so far.
+pub const VERSION: u8 = 0;
+
+/// The payload size recommended for avoiding IP fragmentation.
+/// https://www.dnsflagday.net/2020/
+pub const DEFAULT_UDP_PAYLOAD_SIZE: u16 = 1232;
+
+/// Anything smaller than this is treated as this.
+/// https://tools.ietf.org/html/rfc6891#section-6.2.5
+pub const MIN_UDP_PAYLOAD_SIZE: u16 = 512;
+
+/// The DO bit in the flags.
+/// https://tools.ietf.org/html/rfc3225#section-3
+const DNSSEC_OK: u16 = 0x8000;
+
+/// The fields of the OPT pseudo-record, which reuses the CLASS and TTL of a
+/// normal record for its own purposes.
+/// https://tools.ietf.org/html/rfc6891#section-6.1.3
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edns {
+	/// The largest UDP response the sender can reassemble.
+	pub udp_payload_size: u16,
+	/// The upper 8 bits of the 12 bit RCODE. The lower 4 are in the header.
+	pub extended_rcode: u8,
+	pub version: u8,
+	/// Whether the sender wants DNSSEC records.
+	pub dnssec_ok: bool,
+	pub options: Vec<EdnsOption>,
+}
+
+impl Edns {
+	pub fn new(udp_payload_size: u16) -> Edns {
+		Edns {
+			udp_payload_size,
+			extended_rcode: 0,
+			version: VERSION,
+			dnssec_ok: false,
+			options: Vec::new(),
+		}
+	}
+
+	/// Read the EDNS fields out of an OPT record. Returns `None` for any
+	/// other record.
+	pub fn from_record(rr: &ResourceRecord) -> Option<Edns> {
+		match &rr.rdata {
+			RData::OPT(options) => Some(Edns {
+				udp_payload_size: u16::from(rr.class).max(MIN_UDP_PAYLOAD_SIZE),
+				extended_rcode: (rr.ttl >> 24) as u8,
+				version: (rr.ttl >> 16) as u8,
+				dnssec_ok: rr.ttl as u16 & DNSSEC_OK != 0,
+				options: options.clone(),
+			}),
+			_ => None,
+		}
+	}
+
+	pub fn to_record(&self) -> ResourceRecord {
+		let flags = if self.dnssec_ok { DNSSEC_OK } else { 0 };
+		ResourceRecord {
+			name: Name::root(),
+			rtype: Type::OPT,
+			class: Class::from(self.udp_payload_size),
+			ttl: (self.extended_rcode as u32) << 24 | (self.version as u32) << 16 | flags as u32,
+			rdata: RData::OPT(self.options.clone()),
+		}
+	}
+}
+
+/// Shown the way `dig` shows the OPT pseudosection, with a line for the
+/// fields and one for each option.
+impl fmt::Display for Edns {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let flags = if self.dnssec_ok { " do" } else { "" };
+		write!(
+			f,
+			"; EDNS: version: {}, flags:{}; udp: {}",
+			self.version, flags, self.udp_payload_size
+		)?;
+		for option in &self.options {
+			write!(f, "\n; {}", option)?;
+		}
+		Ok(())
+	}
+}
+
+/// https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-11
+const NSID: u16 = 3;
+const CLIENT_SUBNET: u16 = 8;
+const COOKIE: u16 = 10;
+const PADDING: u16 = 12;
+
+/// An option in the data of an OPT record.
+/// https://tools.ietf.org/html/rfc6891#section-6.1.2
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EdnsOption {
+	/// Empty in a query, asking the server to identify itself in the response.
+	/// https://tools.ietf.org/html/rfc5001
+	Nsid(Vec<u8>),
+	/// https://tools.ietf.org/html/rfc7871#section-6
+	ClientSubnet {
+		source_prefix: u8,
+		scope_prefix: u8,
+		/// Only the first `source_prefix` bits are significant.
+		address: IpAddr,
+	},
+	/// https://tools.ietf.org/html/rfc7873#section-4
+	Cookie {
+		client: [u8; 8],
+		/// Between 8 and 32 bytes, if present.
+		server: Option<Vec<u8>>,
+	},
+	/// A number of zero bytes, to disguise the size of the message.
+	/// https://tools.ietf.org/html/rfc7830
+	Padding(u16),
+	/// An option we don't understand, or one that was malformed.
+	Unknown(u16, Vec<u8>),
+}
+
+impl EdnsOption {
+	pub fn code(&self) -> u16 {
+		match self {
+			EdnsOption::Nsid(_) => NSID,
+			EdnsOption::ClientSubnet { .. } => CLIENT_SUBNET,
+			EdnsOption::Cookie { .. } => COOKIE,
+			EdnsOption::Padding(_) => PADDING,
+			EdnsOption::Unknown(code, _) => *code,
+		}
+	}
+
+	/// Decode a list of options, which is the whole data of an OPT record.
+	pub fn decode_all(r: &mut Reader, len: usize) -> Result<Vec<EdnsOption>> {
+		let data = r.read_bytes(len)?;
+		let mut r = Reader::new(data);
+		let mut options = Vec::new();
+		while r.remaining() > 0 {
+			let code = r.read_u16()?;
+			let len = r.read_u16()?;
+			let data = r.read_bytes(len as usize)?;
+			options.push(EdnsOption::decode(code, data));
+		}
+		Ok(options)
+	}
+
+	/// Options we understand but can't make sense of are kept as `Unknown`
+	/// rather than failing the whole message.
+	fn decode(code: u16, data: &[u8]) -> EdnsOption {
+		let option = match code {
+			NSID => Some(EdnsOption::Nsid(data.to_vec())),
+			CLIENT_SUBNET => decode_client_subnet(data),
+			COOKIE if data.len() == 8 || (16..=40).contains(&data.len()) => {
+				let mut client = [0; 8];
+				client.copy_from_slice(&data[..8]);
+				let server = if data.len() > 8 {
+					Some(data[8..].to_vec())
+				} else {
+					None
+				};
+				Some(EdnsOption::Cookie { client, server })
+			}
+			PADDING => Some(EdnsOption::Padding(data.len() as u16)),
+			_ => None,
+		};
+		option.unwrap_or_else(|| EdnsOption::Unknown(code, data.to_vec()))
+	}
+
+	pub fn encode(&self, w: &mut Writer) -> Result<()> {
+		let mut data = Vec::new();
+		match self {
+			EdnsOption::Nsid(nsid) => data.extend_from_slice(nsid),
+			EdnsOption::ClientSubnet {
+				source_prefix,
+				scope_prefix,
+				address,
+			} => {
+				let (family, octets) = match address {
+					IpAddr::V4(a) => (1u16, a.octets().to_vec()),
+					IpAddr::V6(a) => (2u16, a.octets().to_vec()),
+				};
+				if *source_prefix as usize > octets.len() * 8 {
+					return Err(Error::BadRdLength);
+				}
+				data.extend_from_slice(&family.to_be_bytes());
+				data.push(*source_prefix);
+				data.push(*scope_prefix);
+				// The address is cut down to the bytes the prefix covers, and
+				// the bits past the prefix must be zero.
+				let len = (*source_prefix as usize).div_ceil(8);
+				data.extend_from_slice(&octets[..len]);
+				if source_prefix % 8 != 0 {
+					data[4 + len - 1] &= 0xff << (8 - source_prefix % 8);
+				}
+			}
+			EdnsOption::Cookie { client, server } => {
+				data.extend_from_slice(client);
+				if let Some(server) = server {
+					data.extend_from_slice(server);
+				}
+			}
+			EdnsOption::Padding(len) => data.resize(*len as usize, 0),
+			EdnsOption::Unknown(_, d) => data.extend_from_slice(d),
+		}
+		if data.len() > 0xffff {
+			return Err(Error::BadRdLength);
+		}
+		w.write_u16(self.code());
+		w.write_u16(data.len() as u16);
+		w.write_bytes(&data);
+		Ok(())
+	}
+}
+
+fn decode_client_subnet(data: &[u8]) -> Option<EdnsOption> {
+	if data.len() < 4 {
+		return None;
+	}
+	let family = u16::from_be_bytes([data[0], data[1]]);
+	let source_prefix = data[2];
+	let scope_prefix = data[3];
+	let address = &data[4..];
+	if address.len() != (source_prefix as usize).div_ceil(8) {
+		return None;
+	}
+	let address = match family {
+		1 if address.len() <= 4 => {
+			let mut octets = [0; 4];
+			octets[..address.len()].copy_from_slice(address);
+			IpAddr::V4(Ipv4Addr::from(octets))
+		}
+		2 if address.len() <= 16 => {
+			let mut octets = [0; 16];
+			octets[..address.len()].copy_from_slice(address);
+			IpAddr::V6(Ipv6Addr::from(octets))
+		}
+		_ => return None,
+	};
+	Some(EdnsOption::ClientSubnet {
+		source_prefix,
+		scope_prefix,
+		address,
+	})
+}
+
+/// Shown the way `dig` shows them in the OPT pseudosection.
+impl fmt::Display for EdnsOption {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			EdnsOption::Nsid(nsid) => {
+				write!(f, "NSID: {}", Hex(nsid))?;
+				if !nsid.is_empty() && nsid.iter().all(|b| (0x20..0x7f).contains(b)) {
+					write!(f, " (\"{}\")", String::from_utf8_lossy(nsid))?;
+				}
+				Ok(())
+			}
+			EdnsOption::ClientSubnet {
+				source_prefix,
+				scope_prefix,
+				address,
+			} => write!(
+				f,
+				"CLIENT-SUBNET: {}/{}/{}",
+				address, source_prefix, scope_prefix
+			),
+			EdnsOption::Cookie { client, server } => {
+				write!(f, "COOKIE: {}", Hex(client))?;
+				if let Some(server) = server {
+					write!(f, "{}", Hex(server))?;
+				}
+				Ok(())
+			}
+			EdnsOption::Padding(len) => write!(f, "PAD: ({} bytes)", len),
+			EdnsOption::Unknown(code, data) => {
+				write!(f, "OPT={}: {}", code, Hex(data))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn options_show_uppercase_hex() {
+		let nsid = EdnsOption::Nsid(b"ns1".to_vec());
+		assert_eq!(nsid.to_string(), "NSID: 6E7331 (\"ns1\")");
+		let unknown = EdnsOption::Unknown(65001, vec![0xab, 0x01]);
+		assert_eq!(unknown.to_string(), "OPT=65001: AB01");
+	}
+}
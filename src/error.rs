@@ -0,0 +1,149 @@
+//! The errors returned by everything in this crate
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+
+use crate::name::Name;
+use crate::wire::{Rcode, MAX_LABEL_LEN, MAX_NAME_LEN};
+
+#[derive(Debug)]
+pub enum Error {
+	/// The message ended in the middle of a field.
+	Truncated,
+	/// A label length byte used one of the label types we don't understand.
+	/// https://tools.ietf.org/html/rfc1035#section-4.1.4
+	BadLabelType(u8),
+	/// A compression pointer pointed past the end of the message.
+	PointerOutOfBounds(usize),
+	/// A compression pointer didn't point to an earlier part of the message,
+	/// so following it could loop forever.
+	CompressionLoop(usize),
+	/// A name was longer than 255 bytes.
+	/// https://tools.ietf.org/html/rfc1035#section-2.3.4
+	NameTooLong,
+	/// A label was longer than 63 bytes.
+	LabelTooLong,
+	/// A name had an empty label somewhere other than at the end, like `a..b`.
+	EmptyLabel,
+	/// A name contained a backslash escape that wasn't `\X` or `\DDD`.
+	BadEscape,
+	/// A Unicode name couldn't be converted to ASCII.
+	/// https://www.unicode.org/reports/tr46/#ToASCII
+	BadIdna,
+	/// A character-string was longer than 255 bytes.
+	/// https://tools.ietf.org/html/rfc1035#section-3.3
+	StringTooLong,
+	/// The RDLENGTH of a record didn't match the data it contained.
+	BadRdLength,
+	/// There were bytes left over after the last section of the message.
+	TrailingData,
+	/// A message was too long to send over TCP.
+	MessageTooLong,
+	/// A response over TCP had a different ID to the request. Over UDP these
+	/// are ignored instead, since anyone could have sent them.
+	IdMismatch {
+		expected: u16,
+		received: u16,
+	},
+	/// A response over TCP wasn't a response to the question we asked.
+	QuestionMismatch,
+	/// The server answered, but with an error.
+	/// https://tools.ietf.org/html/rfc1035#section-4.1.1
+	Server(Rcode),
+	/// There were no servers to send the query to.
+	NoNameservers,
+	/// None of the servers for a zone gave a usable answer, while resolving
+	/// iteratively.
+	/// https://tools.ietf.org/html/rfc1912#section-2.8
+	LameDelegation(Name),
+	/// Resolving iteratively took too many referrals, or too many lookups of
+	/// name server addresses inside each other.
+	TooManyReferrals,
+	/// A chain of CNAMEs was too long, and probably loops.
+	CnameLoop,
+	/// None of the servers answered in time.
+	Timeout {
+		servers: Vec<SocketAddr>,
+		attempts: u32,
+	},
+	Io(io::Error),
+}
+
+impl Error {
+	pub fn is_timeout(&self) -> bool {
+		matches!(self, Error::Timeout { .. })
+	}
+}
+
+impl fmt::Display for Error {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Error::Truncated => write!(f, "message is truncated"),
+			Error::BadLabelType(b) => write!(f, "unsupported label type 0x{:02x}", b),
+			Error::PointerOutOfBounds(p) => {
+				write!(f, "compression pointer to {} is out of bounds", p)
+			}
+			Error::CompressionLoop(p) => write!(f, "compression pointer to {} loops", p),
+			Error::NameTooLong => write!(f, "name is longer than {} bytes", MAX_NAME_LEN),
+			Error::LabelTooLong => write!(f, "label is longer than {} bytes", MAX_LABEL_LEN),
+			Error::EmptyLabel => write!(f, "name contains an empty label"),
+			Error::BadEscape => write!(f, "name contains an invalid escape"),
+			Error::BadIdna => write!(f, "name can't be converted with IDNA"),
+			Error::StringTooLong => write!(f, "character-string is longer than 255 bytes"),
+			Error::BadRdLength => write!(f, "record data length mismatch"),
+			Error::TrailingData => write!(f, "trailing data after message"),
+			Error::MessageTooLong => write!(f, "message is too long to send over TCP"),
+			Error::IdMismatch { expected, received } => write!(
+				f,
+				"response has ID {} but the request had {}",
+				received, expected
+			),
+			Error::QuestionMismatch => write!(f, "response doesn't match the question"),
+			Error::Server(rcode) => write!(f, "server responded with {}", rcode),
+			Error::NoNameservers => write!(f, "no nameservers configured"),
+			Error::LameDelegation(zone) => {
+				write!(f, "no server for {} gave a usable answer", zone)
+			}
+			Error::TooManyReferrals => write!(f, "too many referrals"),
+			Error::CnameLoop => write!(f, "CNAME chain is too long"),
+			Error::Timeout { servers, attempts } => {
+				write!(f, "no response from ")?;
+				for (i, server) in servers.iter().enumerate() {
+					if i > 0 {
+						write!(f, ", ")?;
+					}
+					write!(f, "{}", server)?;
+				}
+				write!(f, " after {} attempts", attempts)
+			}
+			Error::Io(e) => write!(f, "{}", e),
+		}
+	}
+}
+
+/// An I/O error is shown as itself rather than wrapped, so it isn't also
+/// given as the source, or reporters that walk the chain would show it twice.
+impl error::Error for Error {}
+
+impl From<io::Error> for Error {
+	fn from(e: io::Error) -> Error {
+		Error::Io(e)
+	}
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::error::Error as _;
+
+	#[test]
+	fn io_errors_are_shown_once() {
+		let e = Error::from(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+		assert_eq!(e.to_string(), "no such file");
+		assert!(e.source().is_none());
+	}
+}
@@ -0,0 +1,347 @@
+//! Internationalized domain names, which are sent as ASCII by encoding each
+//! label containing other characters with Punycode.
+//! https://www.unicode.org/reports/tr46/
+//! https://tools.ietf.org/html/rfc3492
+//!
+//! Only part of the UTS #46 mapping is done, without the Unicode tables:
+//! labels are lowercased, the ideographic and fullwidth full stops are treated
+//! as dots, fullwidth letters and digits and Roman numerals become ASCII, and
+//! default ignorable characters are dropped. Characters that UTS #46 would map
+//! to something else or disallow, as far as we can tell without the tables,
+//! are rejected rather than encoded as they are, as are combining marks that
+//! Normalization Form C would compose with the letter before them.
+
+use crate::error::{Error, Result};
+
+/// The prefix that marks a label as Punycode.
+/// https://tools.ietf.org/html/rfc5890#section-2.3.2.5
+const ACE_PREFIX: &str = "xn--";
+
+/// Characters that UTS #46 maps to a full stop.
+/// https://www.unicode.org/reports/tr46/#Notation
+const DOTS: [char; 4] = ['.', '\u{3002}', '\u{ff0e}', '\u{ff61}'];
+
+/// What `\u{2160}` to `\u{216f}` map to, and their small forms 16 later.
+const ROMAN_NUMERALS: [&str; 16] = [
+	"i", "ii", "iii", "iv", "v", "vi", "vii", "viii", "ix", "x", "xi", "xii", "l", "c", "d", "m",
+];
+
+/// https://tools.ietf.org/html/rfc3492#section-5
+const BASE: u32 = 36;
+const TMIN: u32 = 1;
+const TMAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 0x80;
+
+/// Convert a name in presentation format to one that only uses ASCII. Labels
+/// that are already ASCII are left alone, escapes included.
+pub fn to_ascii(name: &str) -> Result<String> {
+	let mut ascii = String::with_capacity(name.len());
+	let mut label = String::new();
+	let mut chars = name.chars();
+	while let Some(c) = chars.next() {
+		match c {
+			'\\' => {
+				label.push(c);
+				label.extend(chars.next());
+			}
+			c if DOTS.contains(&c) => {
+				push_label(&mut ascii, &label)?;
+				ascii.push('.');
+				label.clear();
+			}
+			c => label.push(c),
+		}
+	}
+	push_label(&mut ascii, &label)?;
+	Ok(ascii)
+}
+
+fn push_label(ascii: &mut String, label: &str) -> Result<()> {
+	if label.is_ascii() {
+		ascii.push_str(label);
+		return Ok(());
+	}
+	// An escape in the middle of Unicode text has no sensible encoding.
+	if label.contains('\\') {
+		return Err(Error::BadIdna);
+	}
+	let label = map_label(label)?;
+	if label.is_ascii() {
+		ascii.push_str(&label);
+		return Ok(());
+	}
+	ascii.push_str(ACE_PREFIX);
+	ascii.push_str(&punycode_encode(&label).ok_or(Error::BadIdna)?);
+	Ok(())
+}
+
+/// Map a label the way UTS #46 does, as far as we can.
+/// https://www.unicode.org/reports/tr46/#Processing
+fn map_label(label: &str) -> Result<String> {
+	let mut mapped = String::with_capacity(label.len());
+	let mut previous = None;
+	for c in label.chars() {
+		match c {
+			'\u{ff0d}'
+			| '\u{ff10}'..='\u{ff19}'
+			| '\u{ff21}'..='\u{ff3a}'
+			| '\u{ff41}'..='\u{ff5a}' => {
+				let ascii = std::char::from_u32(c as u32 - 0xfee0).unwrap();
+				mapped.push(ascii.to_ascii_lowercase());
+			}
+			'\u{2160}'..='\u{217f}' => {
+				mapped.push_str(ROMAN_NUMERALS[(c as usize - 0x2160) % 16]);
+			}
+			c if is_ignored(c) => {}
+			c if is_disallowed(c) => return Err(Error::BadIdna),
+			c if is_combining(c) && previous.unwrap_or('\0') < '\u{500}' => {
+				return Err(Error::BadIdna)
+			}
+			c => mapped.extend(c.to_lowercase()),
+		}
+		previous = Some(c);
+	}
+	Ok(mapped)
+}
+
+/// Characters that UTS #46 maps to nothing.
+fn is_ignored(c: char) -> bool {
+	matches!(
+		c as u32,
+		0xad | 0x34f | 0x180b..=0x180d | 0x200b | 0x2060 | 0xfe00..=0xfe0f | 0xfeff
+	)
+}
+
+/// Characters that UTS #46 disallows, or maps to something we don't know.
+fn is_disallowed(c: char) -> bool {
+	if c.is_ascii() {
+		return !(c.is_ascii_alphanumeric() || c == '-');
+	}
+	c.is_control()
+		|| c.is_whitespace()
+		|| matches!(
+			c as u32,
+			// Latin-1 symbols, and letters that map to more than one.
+			0xa0..=0xbf | 0x132..=0x133 | 0x13f..=0x140 | 0x149 | 0x17f | 0x1c4..=0x1cc | 0x1f1..=0x1f3
+			// Blocks of compatibility characters: superscripts and subscripts,
+			// number forms, enclosed alphanumerics, CJK compatibility,
+			// presentation forms, halfwidth and fullwidth forms, mathematical
+			// alphanumerics and enclosed alphanumeric supplement.
+			| 0x2070..=0x209f | 0x2150..=0x218f | 0x2460..=0x24ff | 0x3300..=0x33ff
+			| 0xf900..=0xfaff | 0xfb00..=0xfb4f | 0xfe30..=0xfe4f | 0xfe70..=0xfeff
+			| 0xff00..=0xffef | 0x1d400..=0x1d7ff | 0x1f100..=0x1f1e5 | 0x2f800..=0x2fa1f
+			// Private use and noncharacters.
+			| 0xe000..=0xf8ff | 0xf0000..=0x10ffff | 0xfdd0..=0xfdef
+		) || c as u32 & 0xfffe == 0xfffe
+}
+
+/// Combining marks, which can't start a label. After Latin, Greek and
+/// Cyrillic letters they are nearly always there because the text isn't in
+/// Normalization Form C.
+/// https://www.unicode.org/reports/tr46/#Validity_Criteria
+fn is_combining(c: char) -> bool {
+	matches!(
+		c as u32,
+		0x300..=0x36f | 0x1ab0..=0x1aff | 0x1dc0..=0x1dff | 0x20d0..=0x20ff | 0xfe20..=0xfe2f
+	)
+}
+
+/// Decode a label in wire format if it is valid Punycode. Returns `None` for
+/// anything else, including plain ASCII labels.
+pub fn label_to_unicode(label: &[u8]) -> Option<String> {
+	let label = std::str::from_utf8(label).ok()?;
+	if label.len() <= ACE_PREFIX.len()
+		|| !label[..ACE_PREFIX.len()].eq_ignore_ascii_case(ACE_PREFIX)
+	{
+		return None;
+	}
+	let decoded = punycode_decode(&label[ACE_PREFIX.len()..].to_ascii_lowercase())?;
+	if decoded.is_ascii() || decoded.contains(&DOTS[..]) {
+		return None;
+	}
+	Some(decoded)
+}
+
+/// https://tools.ietf.org/html/rfc3492#section-6.3
+fn punycode_encode(input: &str) -> Option<String> {
+	let chars: Vec<u32> = input.chars().map(|c| c as u32).collect();
+	let mut output: String = input.chars().filter(char::is_ascii).collect();
+	let basic = output.len() as u32;
+	if basic > 0 {
+		output.push('-');
+	}
+
+	let mut n = INITIAL_N;
+	let mut delta = 0u32;
+	let mut bias = INITIAL_BIAS;
+	let mut handled = basic;
+	while (handled as usize) < chars.len() {
+		let m = *chars.iter().filter(|&&c| c >= n).min()?;
+		delta = delta.checked_add((m - n).checked_mul(handled + 1)?)?;
+		n = m;
+		for &c in &chars {
+			if c < n {
+				delta = delta.checked_add(1)?;
+			}
+			if c == n {
+				let mut q = delta;
+				let mut k = BASE;
+				loop {
+					let t = threshold(k, bias);
+					if q < t {
+						break;
+					}
+					output.push(encode_digit(t + (q - t) % (BASE - t)));
+					q = (q - t) / (BASE - t);
+					k += BASE;
+				}
+				output.push(encode_digit(q));
+				bias = adapt(delta, handled + 1, handled == basic);
+				delta = 0;
+				handled += 1;
+			}
+		}
+		delta += 1;
+		n += 1;
+	}
+	Some(output)
+}
+
+/// https://tools.ietf.org/html/rfc3492#section-6.2
+fn punycode_decode(input: &str) -> Option<String> {
+	let (basic, extended) = match input.rfind('-') {
+		Some(i) => (&input[..i], &input[i + 1..]),
+		None => ("", input),
+	};
+	if !basic.is_ascii() {
+		return None;
+	}
+
+	let mut output: Vec<char> = basic.chars().collect();
+	let mut n = INITIAL_N;
+	let mut i = 0u32;
+	let mut bias = INITIAL_BIAS;
+	let mut digits = extended.bytes().peekable();
+	while digits.peek().is_some() {
+		let old_i = i;
+		let mut w = 1u32;
+		let mut k = BASE;
+		loop {
+			let digit = decode_digit(digits.next()?)?;
+			i = i.checked_add(digit.checked_mul(w)?)?;
+			let t = threshold(k, bias);
+			if digit < t {
+				break;
+			}
+			w = w.checked_mul(BASE - t)?;
+			k += BASE;
+		}
+		let len = output.len() as u32 + 1;
+		bias = adapt(i - old_i, len, old_i == 0);
+		n = n.checked_add(i / len)?;
+		i %= len;
+		output.insert(i as usize, std::char::from_u32(n)?);
+		i += 1;
+	}
+	Some(output.into_iter().collect())
+}
+
+fn threshold(k: u32, bias: u32) -> u32 {
+	if k <= bias {
+		TMIN
+	} else if k >= bias + TMAX {
+		TMAX
+	} else {
+		k - bias
+	}
+}
+
+/// https://tools.ietf.org/html/rfc3492#section-6.1
+fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+	let mut delta = if first { delta / DAMP } else { delta / 2 };
+	delta += delta / points;
+	let mut k = 0;
+	while delta > ((BASE - TMIN) * TMAX) / 2 {
+		delta /= BASE - TMIN;
+		k += BASE;
+	}
+	k + (BASE - TMIN + 1) * delta / (delta + SKEW)
+}
+
+fn encode_digit(d: u32) -> char {
+	match d {
+		0..=25 => (b'a' + d as u8) as char,
+		_ => (b'0' + (d - 26) as u8) as char,
+	}
+}
+
+fn decode_digit(b: u8) -> Option<u32> {
+	match b {
+		b'a'..=b'z' => Some((b - b'a') as u32),
+		b'A'..=b'Z' => Some((b - b'A') as u32),
+		b'0'..=b'9' => Some((b - b'0') as u32 + 26),
+		_ => None,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn encodes_punycode() {
+		assert_eq!(to_ascii("münchen.de").unwrap(), "xn--mnchen-3ya.de");
+		assert_eq!(to_ascii("Bücher.example").unwrap(), "xn--bcher-kva.example");
+		assert_eq!(to_ascii("☃.net").unwrap(), "xn--n3h.net");
+		assert_eq!(to_ascii("例え。テスト").unwrap(), "xn--r8jz45g.xn--zckzah");
+	}
+
+	#[test]
+	fn leaves_ascii_alone() {
+		assert_eq!(to_ascii("Example.COM.").unwrap(), "Example.COM.");
+		assert_eq!(to_ascii("a\\.b").unwrap(), "a\\.b");
+	}
+
+	#[test]
+	fn maps_compatibility_characters() {
+		assert_eq!(to_ascii("ｅｘａｍｐｌｅ．ｃｏｍ").unwrap(), "example.com");
+		assert_eq!(to_ascii("ＥＸＡＭＰＬＥ-１").unwrap(), "example-1");
+		assert_eq!(to_ascii("Ⅻ.com").unwrap(), "xii.com");
+		assert_eq!(to_ascii("ⅿⅰⅹ.com").unwrap(), "mix.com");
+		assert_eq!(to_ascii("ex\u{ad}ample.com").unwrap(), "example.com");
+	}
+
+	#[test]
+	fn rejects_what_it_cannot_map() {
+		for name in &[
+			"①.com",
+			"ｶﾀｶﾅ.com",
+			"ﬁle.com",
+			"x².com",
+			"\u{e000}.com",
+			"ü\\.com",
+			"ü_x.com",
+			"ü x.com",
+		] {
+			assert!(matches!(to_ascii(name), Err(Error::BadIdna)), "{}", name);
+		}
+	}
+
+	#[test]
+	fn rejects_unnormalized_and_leading_marks() {
+		assert!(matches!(to_ascii("mu\u{308}nchen.de"), Err(Error::BadIdna)));
+		assert!(matches!(to_ascii("\u{301}a.de"), Err(Error::BadIdna)));
+	}
+
+	#[test]
+	fn decodes_punycode() {
+		assert_eq!(label_to_unicode(b"xn--mnchen-3ya").unwrap(), "münchen");
+		assert_eq!(label_to_unicode(b"XN--N3H").unwrap(), "☃");
+		assert_eq!(label_to_unicode(b"example"), None);
+		assert_eq!(label_to_unicode(b"xn--abc-"), None);
+		assert_eq!(label_to_unicode(b"xn--99999999999"), None);
+	}
+}
@@ -0,0 +1,236 @@
+//! Messages in JSON
+//! https://tools.ietf.org/html/rfc8427
+
+use std::fmt;
+
+use crate::records::{Hex, RData};
+use crate::wire::{DnsHeaderFlags, Message, Question, ResourceRecord, Writer};
+
+/// Represent a message as a JSON object, with the members described in
+/// https://tools.ietf.org/html/rfc8427#section-2
+///
+/// Record data is given in presentation format in an `rdata` member named
+/// after the type, like `rdataMX`. Types without a presentation format of
+/// their own, including OPT, are given as `RDATAHEX` instead.
+pub fn to_string(message: &Message) -> String {
+	encode_message(message).to_string()
+}
+
+/// A JSON value, just enough to describe a message.
+enum Value {
+	Bool(bool),
+	Int(u64),
+	Str(String),
+	Array(Vec<Value>),
+	Object(Vec<(String, Value)>),
+}
+
+fn object<I: IntoIterator<Item = (&'static str, Value)>>(members: I) -> Value {
+	Value::Object(
+		members
+			.into_iter()
+			.map(|(key, value)| (key.to_string(), value))
+			.collect(),
+	)
+}
+
+/// https://tools.ietf.org/html/rfc8427#section-2.1
+fn encode_message(message: &Message) -> Value {
+	let header = &message.header;
+	let flag = |flag| Value::Bool(header.flags().contains(flag));
+	let sections =
+		|records: &[ResourceRecord]| Value::Array(records.iter().map(encode_record).collect());
+	object(vec![
+		("ID", Value::Int(header.id as u64)),
+		("QR", flag(DnsHeaderFlags::RESPONSE)),
+		("Opcode", Value::Int(u8::from(header.opcode()) as u64)),
+		("AA", flag(DnsHeaderFlags::AUTHORITATIVE)),
+		("TC", flag(DnsHeaderFlags::TRUNCATED)),
+		("RD", flag(DnsHeaderFlags::RECURSION_DESIRED)),
+		("RA", flag(DnsHeaderFlags::RECURSION_AVAILABLE)),
+		("AD", flag(DnsHeaderFlags::AUTHENTIC_DATA)),
+		("CD", flag(DnsHeaderFlags::CHECKING_DISABLED)),
+		("RCODE", Value::Int(u16::from(header.rcode()) as u64)),
+		("QDCOUNT", Value::Int(message.questions.len() as u64)),
+		("ANCOUNT", Value::Int(message.answers.len() as u64)),
+		("NSCOUNT", Value::Int(message.authorities.len() as u64)),
+		("ARCOUNT", Value::Int(message.additionals.len() as u64)),
+		(
+			"questionRRs",
+			Value::Array(message.questions.iter().map(encode_question).collect()),
+		),
+		("answerRRs", sections(&message.answers)),
+		("authorityRRs", sections(&message.authorities)),
+		("additionalRRs", sections(&message.additionals)),
+	])
+}
+
+/// https://tools.ietf.org/html/rfc8427#section-2.2
+fn encode_question(q: &Question) -> Value {
+	object(vec![
+		("NAME", Value::Str(q.name.to_string())),
+		("TYPE", Value::Int(u16::from(q.qtype) as u64)),
+		("TYPEname", Value::Str(q.qtype.to_string())),
+		("CLASS", Value::Int(u16::from(q.qclass) as u64)),
+		("CLASSname", Value::Str(q.qclass.to_string())),
+	])
+}
+
+/// https://tools.ietf.org/html/rfc8427#section-2.2
+fn encode_record(rr: &ResourceRecord) -> Value {
+	let mut members = vec![
+		("NAME".to_string(), Value::Str(rr.name.to_string())),
+		("TYPE".to_string(), Value::Int(u16::from(rr.rtype) as u64)),
+		("TYPEname".to_string(), Value::Str(rr.rtype.to_string())),
+		("CLASS".to_string(), Value::Int(u16::from(rr.class) as u64)),
+		("CLASSname".to_string(), Value::Str(rr.class.to_string())),
+		("TTL".to_string(), Value::Int(rr.ttl as u64)),
+	];
+	match &rr.rdata {
+		RData::OPT(_) | RData::Unknown(_) => {
+			// A writer of its own leaves nothing to compress against.
+			let mut w = Writer::new();
+			let data = match rr.rdata.encode(&mut w) {
+				Ok(()) => w.into_bytes(),
+				Err(_) => Vec::new(),
+			};
+			members.push(("RDLENGTH".to_string(), Value::Int(data.len() as u64)));
+			members.push(("RDATAHEX".to_string(), Value::Str(Hex(&data).to_string())));
+		}
+		rdata => members.push((format!("rdata{}", rr.rtype), Value::Str(rdata.to_string()))),
+	}
+	Value::Object(members)
+}
+
+impl fmt::Display for Value {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Value::Bool(b) => write!(f, "{}", b),
+			Value::Int(i) => write!(f, "{}", i),
+			Value::Str(s) => fmt_string(f, s),
+			Value::Array(values) => {
+				write!(f, "[")?;
+				for (i, value) in values.iter().enumerate() {
+					if i > 0 {
+						write!(f, ",")?;
+					}
+					write!(f, "{}", value)?;
+				}
+				write!(f, "]")
+			}
+			Value::Object(members) => {
+				write!(f, "{{")?;
+				for (i, (key, value)) in members.iter().enumerate() {
+					if i > 0 {
+						write!(f, ",")?;
+					}
+					fmt_string(f, key)?;
+					write!(f, ":{}", value)?;
+				}
+				write!(f, "}}")
+			}
+		}
+	}
+}
+
+/// https://tools.ietf.org/html/rfc8259#section-7
+fn fmt_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+	write!(f, "\"")?;
+	for c in s.chars() {
+		match c {
+			'"' => write!(f, "\\\"")?,
+			'\\' => write!(f, "\\\\")?,
+			'\n' => write!(f, "\\n")?,
+			'\r' => write!(f, "\\r")?,
+			'\t' => write!(f, "\\t")?,
+			c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+			c => write!(f, "{}", c)?,
+		}
+	}
+	write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::edns::EdnsOption;
+	use crate::records::{Class, Type};
+
+	fn rr(rtype: Type, class: Class, rdata: RData) -> ResourceRecord {
+		ResourceRecord {
+			name: "example".parse().unwrap(),
+			rtype,
+			class,
+			ttl: 300,
+			rdata,
+		}
+	}
+
+	#[test]
+	fn uses_rfc8427_members() {
+		let mut message = Message {
+			questions: vec![Question {
+				name: "example".parse().unwrap(),
+				qtype: Type::MX,
+				qclass: Class::IN,
+			}],
+			answers: vec![
+				rr(
+					Type::MX,
+					Class::IN,
+					RData::MX {
+						preference: 10,
+						exchange: "mail.example".parse().unwrap(),
+					},
+				),
+				rr(
+					Type::TXT,
+					Class::IN,
+					RData::TXT(vec![b"say \"hi\"\\".to_vec()]),
+				),
+			],
+			additionals: vec![
+				rr(
+					Type::Unknown(65280),
+					Class::IN,
+					RData::Unknown(vec![1, 0xab]),
+				),
+				rr(
+					Type::OPT,
+					Class::from(1232),
+					RData::OPT(vec![EdnsOption::Nsid(Vec::new())]),
+				),
+			],
+			..Default::default()
+		};
+		message.header.id = 4660;
+		message.header.set_flags(
+			DnsHeaderFlags::RESPONSE
+				| DnsHeaderFlags::RECURSION_DESIRED
+				| DnsHeaderFlags::RECURSION_AVAILABLE,
+		);
+		let expected = concat!(
+			r#"{"ID":4660,"QR":true,"Opcode":0,"AA":false,"TC":false,"RD":true,"RA":true,"#,
+			r#""AD":false,"CD":false,"RCODE":0,"#,
+			r#""QDCOUNT":1,"ANCOUNT":2,"NSCOUNT":0,"ARCOUNT":2,"#,
+			r#""questionRRs":[{"NAME":"example.","TYPE":15,"TYPEname":"MX","#,
+			r#""CLASS":1,"CLASSname":"IN"}],"#,
+			r#""answerRRs":[{"NAME":"example.","TYPE":15,"TYPEname":"MX","#,
+			r#""CLASS":1,"CLASSname":"IN","TTL":300,"rdataMX":"10 mail.example."},"#,
+			r#"{"NAME":"example.","TYPE":16,"TYPEname":"TXT","#,
+			r#""CLASS":1,"CLASSname":"IN","TTL":300,"rdataTXT":"\"say \\\"hi\\\"\\\\\""}],"#,
+			r#""authorityRRs":[],"#,
+			r#""additionalRRs":[{"NAME":"example.","TYPE":65280,"TYPEname":"TYPE65280","#,
+			r#""CLASS":1,"CLASSname":"IN","TTL":300,"RDLENGTH":2,"RDATAHEX":"01AB"},"#,
+			r#"{"NAME":"example.","TYPE":41,"TYPEname":"OPT","#,
+			r#""CLASS":1232,"CLASSname":"CLASS1232","TTL":300,"RDLENGTH":4,"RDATAHEX":"00030000"}]}"#,
+		);
+		assert_eq!(to_string(&message), expected);
+	}
+
+	#[test]
+	fn escapes_strings() {
+		let value = Value::Str("a\"b\\c\td\n\u{1}é".to_string());
+		assert_eq!(value.to_string(), r#""a\"b\\c\td\n\u0001é""#);
+	}
+}
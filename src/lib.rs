@@ -0,0 +1,25 @@
+//! A library for talking to DNS servers
+//! https://en.wikipedia.org/wiki/Domain_Name_System
+//! https://tools.ietf.org/html/rfc1035
+
+#[macro_use]
+extern crate bitflags;
+
+pub mod cache;
+pub mod client;
+pub mod edns;
+pub mod error;
+pub mod idna;
+pub mod json;
+pub mod name;
+pub mod records;
+pub mod resolver;
+pub mod wire;
+
+pub use cache::{Cache, CacheConfig, Hit};
+pub use client::{Client, ResolverConfig};
+pub use error::{Error, Result};
+pub use name::Name;
+pub use records::{Class, RData, Type};
+pub use resolver::{Lookup, Resolver};
+pub use wire::{Message, Question, ResourceRecord};
@@ -2,147 +2,143 @@
 //! https://en.wikipedia.org/wiki/Domain_Name_System
 //! https://tools.ietf.org/html/rfc1035
 
-#[macro_use]
-extern crate bitflags;
-
-#[macro_use]
-extern crate static_assertions;
-
 use std::env;
-use std::io;
-use std::mem::size_of;
-use std::net::UdpSocket;
+use std::process;
 
-use rand::Rng;
+use dns::edns::Edns;
+use dns::json;
+use dns::records::Type;
+use dns::resolver::{IterativeResolver, Step};
+use dns::wire::{hexdump, DnsHeaderFlags};
+use dns::{Client, Message, Question, ResolverConfig, Result};
 
-/// https://tools.ietf.org/html/rfc1035#section-4.1.1
-#[derive(Copy, Clone, Debug, Default)]
-#[repr(packed)]
-struct DnsHeader {
-	id: u16,
-	flags: u16,
-	qcount: u16,
-	ancount: u16,
-	nscount: u16,
-	arcount: u16,
-}
+mod cli;
 
-const_assert_eq!(size_of::<DnsHeader>(), 12);
+use cli::{Command, Format};
 
-bitflags! {
-	/// https://tools.ietf.org/html/rfc1035#section-4.1.1
-	struct DnsHeaderFlags : u16 {
-		const RESPONSE = 0x1;
+fn main() {
+	let options = match cli::parse(env::args().skip(1)) {
+		Ok(Command::Query(options)) => options,
+		Ok(Command::Help) => {
+			println!("{}", cli::USAGE);
+			return;
+		}
+		Err(e) => {
+			eprintln!("{}\n\n{}", e, cli::USAGE);
+			process::exit(1);
+		}
+	};
 
-		const RECURSION_DESIRED = 0x100;
+	if let Err(e) = run(&options) {
+		eprintln!("dns: {}", e);
+		process::exit(1);
 	}
 }
 
-/// https://tools.ietf.org/html/rfc1035#section-3.2.2
-#[repr(u16)]
-#[allow(dead_code)]
-enum Type {
-	A = 1,
-	NS = 2,
-	MD = 3,
-	MF = 4,
-	CNAME = 5,
-	SOA = 6,
-	MB = 7,
-	MG = 8,
-	MR = 9,
-	NULL = 10,
-	WKS = 11,
-	PTR = 12,
-	HINFO = 13,
-	MINFO = 14,
-	MX = 15,
-	TXT = 16,
-}
-
-///https://tools.ietf.org/html/rfc1035#section-3.2.4
-#[repr(u16)]
-#[allow(dead_code)]
-enum Class {
-	IN = 1,
-	CS = 2,
-	CH = 3,
-	HS = 4,
-}
-
-fn as_u8_slice<T>(x: &T) -> &[u8] {
-	unsafe { std::slice::from_raw_parts(x as *const T as *const u8, size_of::<T>()) }
-}
-
-fn hexdump(data: &[u8]) {
-	for (i, d) in data.chunks(16).enumerate() {
-		print!("{:04x}  ", i * 16);
-		for ch in d.chunks(8) {
-			for x in ch {
-				print!("{:02x} ", x);
-			}
-			print!(" ");
-		}
-		println!();
+fn run(options: &cli::Options) -> Result<()> {
+	let mut config = ResolverConfig::from_system()?;
+	options.apply_to(&mut config)?;
+	if options.trace {
+		let resolver = IterativeResolver::new(options.iterative_config(&config));
+		resolver.trace(&options.name, options.qtype, |step| {
+			print_step(step, options.format)
+		})?;
+		return Ok(());
 	}
-}
-
-fn main() -> io::Result<()> {
-	let query = env::args().nth(1).unwrap_or_else(|| {
-		println!("No query specified, using default");
-		"www.google.com".to_string()
-	});
-	println!("Query: {}", query);
-
-	let socket = UdpSocket::bind("0.0.0.0:1234")?;
-
-	// Put your router IP here
-	socket.connect("192.168.1.254:53")?;
-
-	let buf_size = size_of::<DnsHeader>() + 6 + query.len();
-	let mut data = Vec::with_capacity(buf_size);
 
-	let hdr = DnsHeader {
-		id: rand::thread_rng().gen_range(0, 63335),
-		flags: DnsHeaderFlags::RECURSION_DESIRED.bits().to_be(),
-		qcount: 1u16.to_be(),
+	let mut request = Message {
+		questions: vec![Question {
+			name: options.name.clone(),
+			qtype: options.qtype,
+			qclass: options.qclass,
+		}],
 		..Default::default()
 	};
-
-	// Write DNS header
-	data.extend_from_slice(as_u8_slice(&hdr));
-
-	// Format query
-	// The URL is formatted by splitting it at '.' and replace the '.' with the length in bytes until the next '.'
-	for label in query.split('.') {
-		data.extend_from_slice(&[label.len() as u8]);
-		data.extend_from_slice(label.as_bytes());
+	if options.recurse {
+		request.header.set_flags(DnsHeaderFlags::RECURSION_DESIRED);
+	}
+	if let Some(size) = config.edns_udp_size {
+		let edns_options = options.edns_options();
+		if !edns_options.is_empty() {
+			let mut edns = Edns::new(size);
+			edns.options = edns_options;
+			request.set_edns(Some(edns));
+		}
 	}
-	// After the last chunk, there is a zero to show there is no more data.
-	data.push(0u8);
 
-	// Write the query type and class
-	data.extend_from_slice(&(Type::A as u16).to_be_bytes());
-	data.extend_from_slice(&(Class::IN as u16).to_be_bytes());
+	let client = Client::new(config);
+	let response = client.query(&request)?;
 
-	println!("Hexdump of DNS request:");
-	hexdump(&data);
+	match options.format {
+		Format::Hex => {
+			println!("Hexdump of DNS request:");
+			print!("{}", hexdump(&response.request));
+			println!("Hexdump of DNS response:");
+			print!("{}", hexdump(&response.data));
+		}
+		Format::Json => println!("{}", json::to_string(&response.message)),
+		Format::Text => {
+			print!("{}", response.message);
+			println!();
+			println!(";; Query time: {} msec", response.time.as_millis());
+			println!(
+				";; SERVER: {}#{}({}) ({})",
+				response.server.ip(),
+				response.server.port(),
+				response.server.ip(),
+				response.protocol
+			);
+			println!(";; MSG SIZE  rcvd: {}", response.data.len());
+		}
+	}
 
-	println!("Sending request...");
-	socket.send(&data)?;
-	println!("Request sent.");
+	Ok(())
+}
 
-	let response = {
-		let mut response = vec![0; 4096];
-		println!("Waiting for response...");
-		let length = socket.recv(&mut response)?;
-		println!("Response arrived.");
-		response.truncate(length);
-		response.into_boxed_slice()
+/// Show one response from `--trace`, like `dig +trace` does.
+fn print_step(step: &Step, format: Format) {
+	// Like dig, leave out the lookups of name server addresses on the way.
+	if step.depth > 0 {
+		return;
+	}
+	let response = match step.response {
+		Ok(response) => response,
+		Err(e) => {
+			println!(
+				";; No answer from {}#{}({}) for {}: {}",
+				step.addr.ip(),
+				step.addr.port(),
+				step.server,
+				step.zone,
+				e
+			);
+			println!();
+			return;
+		}
 	};
-
-	println!("Hexdump of DNS response:");
-	hexdump(&response);
-
-	Ok(())
+	match format {
+		Format::Hex => {
+			println!("Hexdump of DNS response from {}:", step.addr);
+			print!("{}", hexdump(&response.data));
+		}
+		Format::Json => println!("{}", json::to_string(&response.message)),
+		Format::Text => {
+			let message = &response.message;
+			for rr in message.answers.iter().chain(&message.authorities) {
+				if rr.rtype != Type::OPT {
+					println!("{}", rr);
+				}
+			}
+			println!(
+				";; Received {} bytes from {}#{}({}) in {} ms, status: {}",
+				response.data.len(),
+				step.addr.ip(),
+				step.addr.port(),
+				step.server,
+				response.time.as_millis(),
+				message.header.rcode()
+			);
+			println!();
+		}
+	}
 }
@@ -0,0 +1,440 @@
+//! Domain names
+//! https://tools.ietf.org/html/rfc1035#section-3.1
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::idna;
+use crate::wire::{MAX_LABEL_LEN, MAX_NAME_LEN};
+
+/// A fully qualified domain name, kept as its labels without the empty root
+/// label at the end. Every `Name` is valid: no label is empty or longer than
+/// 63 bytes, and the whole name fits in 255 bytes on the wire.
+///
+/// Names are compared without regard to ASCII case, and ordered canonically.
+/// https://tools.ietf.org/html/rfc4343
+/// https://tools.ietf.org/html/rfc4034#section-6.1
+#[derive(Clone)]
+pub struct Name {
+	labels: Vec<Vec<u8>>,
+}
+
+impl Name {
+	/// The name `.`, which has no labels.
+	pub fn root() -> Name {
+		Name { labels: Vec::new() }
+	}
+
+	/// Build a name out of labels, starting with the leftmost.
+	pub fn from_labels<I, L>(labels: I) -> Result<Name>
+	where
+		I: IntoIterator<Item = L>,
+		L: Into<Vec<u8>>,
+	{
+		let labels: Vec<Vec<u8>> = labels.into_iter().map(Into::into).collect();
+		let mut wire_len = 1;
+		for label in &labels {
+			if label.is_empty() {
+				return Err(Error::EmptyLabel);
+			}
+			if label.len() > MAX_LABEL_LEN {
+				return Err(Error::LabelTooLong);
+			}
+			wire_len += 1 + label.len();
+		}
+		if wire_len > MAX_NAME_LEN {
+			return Err(Error::NameTooLong);
+		}
+		Ok(Name { labels })
+	}
+
+	pub fn is_root(&self) -> bool {
+		self.labels.is_empty()
+	}
+
+	/// The labels from left to right, not including the root.
+	pub fn labels(&self) -> impl DoubleEndedIterator<Item = &[u8]> + ExactSizeIterator {
+		self.labels.iter().map(Vec::as_slice)
+	}
+
+	/// The number of labels, not including the root.
+	pub fn label_count(&self) -> usize {
+		self.labels.len()
+	}
+
+	/// The name with its leftmost label removed, or `None` for the root.
+	pub fn parent(&self) -> Option<Name> {
+		if self.is_root() {
+			return None;
+		}
+		Some(Name {
+			labels: self.labels[1..].to_vec(),
+		})
+	}
+
+	/// Whether this name is `suffix` or somewhere below it. Every name ends
+	/// with the root.
+	pub fn ends_with(&self, suffix: &Name) -> bool {
+		self.labels.len() >= suffix.labels.len()
+			&& self
+				.labels()
+				.rev()
+				.zip(suffix.labels().rev())
+				.all(|(a, b)| a.eq_ignore_ascii_case(b))
+	}
+
+	/// Whether this name is exactly one label below `parent`.
+	pub fn is_child_of(&self, parent: &Name) -> bool {
+		self.labels.len() == parent.labels.len() + 1 && self.ends_with(parent)
+	}
+
+	/// This name followed by `suffix`, treating this one as relative. Fails
+	/// if the result would be too long.
+	pub fn concat(&self, suffix: &Name) -> Result<Name> {
+		if self.wire_len() + suffix.wire_len() - 1 > MAX_NAME_LEN {
+			return Err(Error::NameTooLong);
+		}
+		let mut labels = self.labels.clone();
+		labels.extend_from_slice(&suffix.labels);
+		Ok(Name { labels })
+	}
+
+	/// The name with its labels lowercased, as used when the exact bytes
+	/// matter, like in DNSSEC signatures.
+	/// https://tools.ietf.org/html/rfc4034#section-6.2
+	pub fn to_lowercase(&self) -> Name {
+		Name {
+			labels: self.labels.iter().map(|l| l.to_ascii_lowercase()).collect(),
+		}
+	}
+
+	/// The length of the name in uncompressed wire format.
+	pub fn wire_len(&self) -> usize {
+		self.labels
+			.iter()
+			.map(|label| 1 + label.len())
+			.sum::<usize>()
+			+ 1
+	}
+
+	/// Parse a name in presentation format, and say whether it was written
+	/// fully qualified, ending with a dot that isn't escaped.
+	pub(crate) fn parse_qualified(s: &str) -> Result<(Name, bool)> {
+		let (labels, qualified) = if s.is_ascii() {
+			parse_name(s)?
+		} else {
+			parse_name(&idna::to_ascii(s)?)?
+		};
+		Ok((Name { labels }, qualified))
+	}
+
+	/// The name in presentation format, with any Punycode labels decoded.
+	pub fn to_unicode(&self) -> String {
+		if self.is_root() {
+			return ".".to_string();
+		}
+		let mut name = String::new();
+		for label in &self.labels {
+			match idna::label_to_unicode(label) {
+				Some(label) => name.push_str(&label),
+				None => push_label(&mut name, label),
+			}
+			name.push('.');
+		}
+		name
+	}
+}
+
+impl PartialEq for Name {
+	fn eq(&self, other: &Name) -> bool {
+		self.labels.len() == other.labels.len() && self.ends_with(other)
+	}
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		state.write_usize(self.labels.len());
+		for label in &self.labels {
+			state.write_u8(label.len() as u8);
+			for b in label {
+				state.write_u8(b.to_ascii_lowercase());
+			}
+		}
+	}
+}
+
+/// Names are sorted by their rightmost label first, with labels compared as
+/// lowercase bytes. A name sorts before the names below it.
+/// https://tools.ietf.org/html/rfc4034#section-6.1
+impl Ord for Name {
+	fn cmp(&self, other: &Name) -> Ordering {
+		let mut a = self.labels().rev();
+		let mut b = other.labels().rev();
+		loop {
+			match (a.next(), b.next()) {
+				(Some(x), Some(y)) => {
+					let x = x.iter().map(u8::to_ascii_lowercase);
+					let y = y.iter().map(u8::to_ascii_lowercase);
+					match x.cmp(y) {
+						Ordering::Equal => {}
+						ordering => return ordering,
+					}
+				}
+				(x, y) => return x.is_some().cmp(&y.is_some()),
+			}
+		}
+	}
+}
+
+impl PartialOrd for Name {
+	fn partial_cmp(&self, other: &Name) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+/// Parses a name in presentation format. The trailing dot is optional, since
+/// every `Name` is fully qualified. Unicode labels are converted to Punycode.
+impl FromStr for Name {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Name> {
+		Name::parse_qualified(s).map(|(name, _)| name)
+	}
+}
+
+/// Shows the name in presentation format, with a trailing dot.
+impl fmt::Display for Name {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.is_root() {
+			return write!(f, ".");
+		}
+		let mut name = String::new();
+		for label in &self.labels {
+			push_label(&mut name, label);
+			name.push('.');
+		}
+		write!(f, "{}", name)
+	}
+}
+
+impl fmt::Debug for Name {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Name({:?})", self.to_string())
+	}
+}
+
+/// Append a label to a name in presentation format, escaping dots,
+/// backslashes and anything that isn't printable ASCII.
+/// https://tools.ietf.org/html/rfc1035#section-5.1
+fn push_label(name: &mut String, label: &[u8]) {
+	for &b in label {
+		match b {
+			b'.' | b'\\' => {
+				name.push('\\');
+				name.push(b as char);
+			}
+			0x21..=0x7e => name.push(b as char),
+			_ => name.push_str(&format!("\\{:03}", b)),
+		}
+	}
+}
+
+/// Split a name in presentation format into its labels, undoing any escapes,
+/// and say whether it ended with an unescaped dot. The dot is optional, and
+/// both `""` and `"."` are the root.
+/// https://tools.ietf.org/html/rfc1035#section-5.1
+fn parse_name(name: &str) -> Result<(Vec<Vec<u8>>, bool)> {
+	let mut labels = Vec::new();
+	if name == "." {
+		return Ok((labels, true));
+	}
+
+	let mut label = Vec::new();
+	let mut wire_len = 1;
+	let mut qualified = false;
+	let mut bytes = name.bytes().peekable();
+	while let Some(b) = bytes.next() {
+		qualified = b == b'.';
+		match b {
+			b'.' => {
+				if label.is_empty() {
+					return Err(Error::EmptyLabel);
+				}
+				wire_len += 1 + label.len();
+				labels.push(std::mem::take(&mut label));
+			}
+			b'\\' => match bytes.next() {
+				Some(d) if d.is_ascii_digit() => {
+					let mut value = (d - b'0') as u32;
+					for _ in 0..2 {
+						match bytes.next() {
+							Some(d) if d.is_ascii_digit() => value = value * 10 + (d - b'0') as u32,
+							_ => return Err(Error::BadEscape),
+						}
+					}
+					if value > 0xff {
+						return Err(Error::BadEscape);
+					}
+					label.push(value as u8);
+				}
+				Some(c) => label.push(c),
+				None => return Err(Error::BadEscape),
+			},
+			_ => label.push(b),
+		}
+		if label.len() > MAX_LABEL_LEN {
+			return Err(Error::LabelTooLong);
+		}
+	}
+	if !label.is_empty() {
+		wire_len += 1 + label.len();
+		labels.push(label);
+	}
+
+	if wire_len > MAX_NAME_LEN {
+		return Err(Error::NameTooLong);
+	}
+	Ok((labels, qualified))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::hash_map::DefaultHasher;
+
+	fn name(s: &str) -> Name {
+		s.parse().unwrap()
+	}
+
+	fn hash(name: &Name) -> u64 {
+		let mut hasher = DefaultHasher::new();
+		name.hash(&mut hasher);
+		hasher.finish()
+	}
+
+	#[test]
+	fn case_is_ignored() {
+		let (a, b) = (name("WWW.Example"), name("www.example."));
+		assert_eq!(a, b);
+		assert_eq!(hash(&a), hash(&b));
+		assert_eq!(a.cmp(&b), Ordering::Equal);
+		assert_eq!(a.to_string(), "WWW.Example.");
+		assert_ne!(name("www.example"), name("www.example.com"));
+		assert_ne!(name("a.b"), name("ab"));
+		assert_ne!(hash(&name("a.b")), hash(&name("ab")));
+	}
+
+	#[test]
+	fn sorts_in_canonical_order() {
+		let sorted = [
+			"example",
+			"a.example",
+			"yljkjljk.a.example",
+			"Z.a.example",
+			"zABC.a.EXAMPLE",
+			"z.example",
+			"\\001.z.example",
+			"*.z.example",
+			"\\200.z.example",
+		];
+		let names: Vec<Name> = sorted.iter().map(|s| name(s)).collect();
+		let mut shuffled = names.clone();
+		shuffled.reverse();
+		shuffled.swap(1, 5);
+		shuffled.sort();
+		assert_eq!(shuffled, names);
+		for pair in names.windows(2) {
+			assert!(pair[0] < pair[1], "{} < {}", pair[0], pair[1]);
+		}
+	}
+
+	#[test]
+	fn relationships() {
+		let www = name("www.Example.com");
+		assert!(www.ends_with(&name("example.COM")));
+		assert!(www.ends_with(&www));
+		assert!(www.ends_with(&Name::root()));
+		assert!(!www.ends_with(&name("ample.com")));
+		assert!(!name("com").ends_with(&www));
+
+		assert!(www.is_child_of(&name("example.com")));
+		assert!(!www.is_child_of(&name("com")));
+		assert!(!www.is_child_of(&www));
+
+		assert_eq!(www.parent(), Some(name("example.com")));
+		assert_eq!(name("com").parent(), Some(Name::root()));
+		assert_eq!(Name::root().parent(), None);
+	}
+
+	#[test]
+	fn concat_checks_length() {
+		assert_eq!(
+			name("www").concat(&name("example.com")).unwrap(),
+			name("www.example.com")
+		);
+		assert_eq!(name("www").concat(&Name::root()).unwrap(), name("www"));
+
+		// Each is 128 bytes with the root, so together they're 255.
+		let label = "a".repeat(63);
+		let half = name(&format!("{}.{}", label, "a".repeat(62)));
+		assert_eq!(half.wire_len(), 128);
+		assert_eq!(half.concat(&half).unwrap().wire_len(), 255);
+		let longer = name(&format!("{}.{}", label, label));
+		assert!(matches!(half.concat(&longer), Err(Error::NameTooLong)));
+	}
+
+	#[test]
+	fn parses_presentation_format() {
+		assert_eq!(
+			parse_name("www.example.").unwrap(),
+			(vec![b"www".to_vec(), b"example".to_vec()], true)
+		);
+		assert_eq!(
+			parse_name("www.example").unwrap(),
+			(vec![b"www".to_vec(), b"example".to_vec()], false)
+		);
+		assert_eq!(
+			parse_name("a\\.b.ex\\097mple").unwrap(),
+			(vec![b"a.b".to_vec(), b"example".to_vec()], false)
+		);
+		assert_eq!(
+			parse_name("\\000\\\\").unwrap(),
+			(vec![vec![0, b'\\']], false)
+		);
+		// An escaped backslash before the dot doesn't escape the dot, but an
+		// escaped dot is part of the label.
+		assert_eq!(parse_name("a\\\\.").unwrap(), (vec![b"a\\".to_vec()], true));
+		assert_eq!(parse_name("a\\.").unwrap(), (vec![b"a.".to_vec()], false));
+		assert_eq!(parse_name(".").unwrap(), (Vec::new(), true));
+		assert_eq!(parse_name("").unwrap(), (Vec::new(), false));
+	}
+
+	#[test]
+	fn rejects_invalid_names() {
+		let long_label = "a".repeat(300);
+		assert!(matches!(parse_name(&long_label), Err(Error::LabelTooLong)));
+		assert!(matches!(
+			parse_name(&"a".repeat(64)),
+			Err(Error::LabelTooLong)
+		));
+		assert!(parse_name(&"a".repeat(63)).is_ok());
+
+		assert!(matches!(parse_name("a..b"), Err(Error::EmptyLabel)));
+		assert!(matches!(parse_name(".a"), Err(Error::EmptyLabel)));
+
+		assert!(matches!(parse_name("\\256"), Err(Error::BadEscape)));
+		assert!(matches!(parse_name("\\25"), Err(Error::BadEscape)));
+		assert!(matches!(parse_name("a\\"), Err(Error::BadEscape)));
+
+		// Four 63 byte labels take 256 bytes on the wire with the root.
+		let label = "a".repeat(63);
+		let long_name = [label.as_str(); 4].join(".");
+		assert!(matches!(parse_name(&long_name), Err(Error::NameTooLong)));
+		assert!(parse_name(&long_name[2..]).is_ok());
+	}
+}
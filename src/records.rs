@@ -0,0 +1,1220 @@
+//! Record types and their data
+//! https://tools.ietf.org/html/rfc1035#section-3.3
+
+use std::error;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use crate::edns::EdnsOption;
+use crate::error::{Error, Result};
+use crate::name::Name;
+use crate::wire::{Reader, Writer};
+
+/// https://tools.ietf.org/html/rfc1035#section-3.2.2
+/// https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-4
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Type {
+	A,
+	NS,
+	MD,
+	MF,
+	CNAME,
+	SOA,
+	MB,
+	MG,
+	MR,
+	NULL,
+	WKS,
+	PTR,
+	HINFO,
+	MINFO,
+	MX,
+	TXT,
+	/// https://tools.ietf.org/html/rfc3596
+	AAAA,
+	/// https://tools.ietf.org/html/rfc2782
+	SRV,
+	/// https://tools.ietf.org/html/rfc3403
+	NAPTR,
+	/// https://tools.ietf.org/html/rfc6672
+	DNAME,
+	/// A pseudo-record carrying EDNS information.
+	/// https://tools.ietf.org/html/rfc6891#section-6
+	OPT,
+	/// https://tools.ietf.org/html/rfc4255
+	SSHFP,
+	/// https://tools.ietf.org/html/rfc6698
+	TLSA,
+	/// https://tools.ietf.org/html/rfc9460
+	SVCB,
+	/// https://tools.ietf.org/html/rfc9460
+	HTTPS,
+	/// https://tools.ietf.org/html/rfc7553
+	URI,
+	/// https://tools.ietf.org/html/rfc8659
+	CAA,
+	/// Only valid in questions, asking for records of every type.
+	/// https://tools.ietf.org/html/rfc1035#section-3.2.3
+	ANY,
+	/// Any type we don't know about, by number.
+	Unknown(u16),
+}
+
+impl From<u16> for Type {
+	fn from(x: u16) -> Type {
+		match x {
+			1 => Type::A,
+			2 => Type::NS,
+			3 => Type::MD,
+			4 => Type::MF,
+			5 => Type::CNAME,
+			6 => Type::SOA,
+			7 => Type::MB,
+			8 => Type::MG,
+			9 => Type::MR,
+			10 => Type::NULL,
+			11 => Type::WKS,
+			12 => Type::PTR,
+			13 => Type::HINFO,
+			14 => Type::MINFO,
+			15 => Type::MX,
+			16 => Type::TXT,
+			28 => Type::AAAA,
+			33 => Type::SRV,
+			35 => Type::NAPTR,
+			39 => Type::DNAME,
+			41 => Type::OPT,
+			44 => Type::SSHFP,
+			52 => Type::TLSA,
+			64 => Type::SVCB,
+			65 => Type::HTTPS,
+			256 => Type::URI,
+			257 => Type::CAA,
+			255 => Type::ANY,
+			_ => Type::Unknown(x),
+		}
+	}
+}
+
+impl From<Type> for u16 {
+	fn from(t: Type) -> u16 {
+		match t {
+			Type::A => 1,
+			Type::NS => 2,
+			Type::MD => 3,
+			Type::MF => 4,
+			Type::CNAME => 5,
+			Type::SOA => 6,
+			Type::MB => 7,
+			Type::MG => 8,
+			Type::MR => 9,
+			Type::NULL => 10,
+			Type::WKS => 11,
+			Type::PTR => 12,
+			Type::HINFO => 13,
+			Type::MINFO => 14,
+			Type::MX => 15,
+			Type::TXT => 16,
+			Type::AAAA => 28,
+			Type::SRV => 33,
+			Type::NAPTR => 35,
+			Type::DNAME => 39,
+			Type::OPT => 41,
+			Type::SSHFP => 44,
+			Type::TLSA => 52,
+			Type::SVCB => 64,
+			Type::HTTPS => 65,
+			Type::URI => 256,
+			Type::CAA => 257,
+			Type::ANY => 255,
+			Type::Unknown(x) => x,
+		}
+	}
+}
+
+/// Types we know are shown by name, and the rest in the generic `TYPEnnn`
+/// form.
+/// https://tools.ietf.org/html/rfc3597#section-5
+impl fmt::Display for Type {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Type::Unknown(x) => write!(f, "TYPE{}", x),
+			_ => write!(f, "{:?}", self),
+		}
+	}
+}
+
+impl Type {
+	/// Every type with a name, so they can be looked up by it.
+	const KNOWN: [Type; 28] = [
+		Type::A,
+		Type::NS,
+		Type::MD,
+		Type::MF,
+		Type::CNAME,
+		Type::SOA,
+		Type::MB,
+		Type::MG,
+		Type::MR,
+		Type::NULL,
+		Type::WKS,
+		Type::PTR,
+		Type::HINFO,
+		Type::MINFO,
+		Type::MX,
+		Type::TXT,
+		Type::AAAA,
+		Type::SRV,
+		Type::NAPTR,
+		Type::DNAME,
+		Type::OPT,
+		Type::SSHFP,
+		Type::TLSA,
+		Type::SVCB,
+		Type::HTTPS,
+		Type::URI,
+		Type::CAA,
+		Type::ANY,
+	];
+}
+
+/// Accepts a type name in any case, or the generic `TYPEnnn` form.
+impl FromStr for Type {
+	type Err = MnemonicError;
+
+	fn from_str(s: &str) -> std::result::Result<Type, MnemonicError> {
+		if let Some(x) = parse_generic(s, "TYPE") {
+			return Ok(Type::from(x));
+		}
+		Type::KNOWN
+			.iter()
+			.find(|t| t.to_string().eq_ignore_ascii_case(s))
+			.copied()
+			.ok_or_else(|| MnemonicError(s.to_string()))
+	}
+}
+
+///https://tools.ietf.org/html/rfc1035#section-3.2.4
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum Class {
+	IN,
+	CS,
+	CH,
+	HS,
+	/// Only valid in questions, asking for records of every class.
+	/// https://tools.ietf.org/html/rfc1035#section-3.2.5
+	ANY,
+	/// Any class we don't know about, by number.
+	Unknown(u16),
+}
+
+impl Class {
+	const KNOWN: [Class; 5] = [Class::IN, Class::CS, Class::CH, Class::HS, Class::ANY];
+}
+
+impl From<u16> for Class {
+	fn from(x: u16) -> Class {
+		match x {
+			1 => Class::IN,
+			2 => Class::CS,
+			3 => Class::CH,
+			4 => Class::HS,
+			255 => Class::ANY,
+			_ => Class::Unknown(x),
+		}
+	}
+}
+
+impl From<Class> for u16 {
+	fn from(c: Class) -> u16 {
+		match c {
+			Class::IN => 1,
+			Class::CS => 2,
+			Class::CH => 3,
+			Class::HS => 4,
+			Class::ANY => 255,
+			Class::Unknown(x) => x,
+		}
+	}
+}
+
+/// https://tools.ietf.org/html/rfc3597#section-5
+impl fmt::Display for Class {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Class::Unknown(x) => write!(f, "CLASS{}", x),
+			_ => write!(f, "{:?}", self),
+		}
+	}
+}
+
+/// Accepts a class name in any case, or the generic `CLASSnnn` form.
+impl FromStr for Class {
+	type Err = MnemonicError;
+
+	fn from_str(s: &str) -> std::result::Result<Class, MnemonicError> {
+		if let Some(x) = parse_generic(s, "CLASS") {
+			return Ok(Class::from(x));
+		}
+		Class::KNOWN
+			.iter()
+			.find(|c| c.to_string().eq_ignore_ascii_case(s))
+			.copied()
+			.ok_or_else(|| MnemonicError(s.to_string()))
+	}
+}
+
+/// Parse the number out of `TYPEnnn` or `CLASSnnn`.
+fn parse_generic(s: &str, prefix: &str) -> Option<u16> {
+	if s.len() > prefix.len() && s[..prefix.len()].eq_ignore_ascii_case(prefix) {
+		s[prefix.len()..].parse().ok()
+	} else {
+		None
+	}
+}
+
+/// A type or class name that we didn't recognise.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MnemonicError(String);
+
+impl fmt::Display for MnemonicError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "unknown type or class {:?}", self.0)
+	}
+}
+
+impl error::Error for MnemonicError {}
+
+/// The data of a resource record, decoded according to its type.
+/// https://tools.ietf.org/html/rfc1035#section-3.3
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::upper_case_acronyms)]
+pub enum RData {
+	/// https://tools.ietf.org/html/rfc1035#section-3.4.1
+	A(Ipv4Addr),
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.11
+	NS(Name),
+	/// Obsolete, replaced by MX.
+	MD(Name),
+	/// Obsolete, replaced by MX.
+	MF(Name),
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.1
+	CNAME(Name),
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.13
+	SOA {
+		mname: Name,
+		rname: Name,
+		serial: u32,
+		refresh: u32,
+		retry: u32,
+		expire: u32,
+		minimum: u32,
+	},
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.3
+	MB(Name),
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.6
+	MG(Name),
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.8
+	MR(Name),
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.10
+	NULL(Vec<u8>),
+	/// https://tools.ietf.org/html/rfc1035#section-3.4.2
+	WKS {
+		address: Ipv4Addr,
+		protocol: u8,
+		/// Bit n is set if port n is served, most significant bit first.
+		bitmap: Vec<u8>,
+	},
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.12
+	PTR(Name),
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.2
+	HINFO { cpu: Vec<u8>, os: Vec<u8> },
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.7
+	MINFO { rmailbx: Name, emailbx: Name },
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.9
+	MX { preference: u16, exchange: Name },
+	/// https://tools.ietf.org/html/rfc1035#section-3.3.14
+	TXT(Vec<Vec<u8>>),
+	/// https://tools.ietf.org/html/rfc3596#section-2.2
+	AAAA(Ipv6Addr),
+	/// https://tools.ietf.org/html/rfc2782
+	SRV {
+		priority: u16,
+		weight: u16,
+		port: u16,
+		target: Name,
+	},
+	/// https://tools.ietf.org/html/rfc3403#section-4.1
+	NAPTR {
+		order: u16,
+		preference: u16,
+		flags: Vec<u8>,
+		services: Vec<u8>,
+		regexp: Vec<u8>,
+		replacement: Name,
+	},
+	/// https://tools.ietf.org/html/rfc6672#section-2.1
+	DNAME(Name),
+	/// https://tools.ietf.org/html/rfc6891#section-6.1.2
+	OPT(Vec<EdnsOption>),
+	/// https://tools.ietf.org/html/rfc4255#section-3.1
+	SSHFP {
+		algorithm: u8,
+		fingerprint_type: u8,
+		fingerprint: Vec<u8>,
+	},
+	/// https://tools.ietf.org/html/rfc6698#section-2.1
+	TLSA {
+		usage: u8,
+		selector: u8,
+		matching_type: u8,
+		data: Vec<u8>,
+	},
+	/// https://tools.ietf.org/html/rfc9460#section-2.2
+	SVCB(Svcb),
+	/// https://tools.ietf.org/html/rfc9460#section-9
+	HTTPS(Svcb),
+	/// https://tools.ietf.org/html/rfc7553#section-4.5
+	URI {
+		priority: u16,
+		weight: u16,
+		target: Vec<u8>,
+	},
+	/// https://tools.ietf.org/html/rfc8659#section-4.1
+	CAA {
+		flags: u8,
+		tag: Vec<u8>,
+		value: Vec<u8>,
+	},
+	/// Data of a type we don't know how to decode, kept as it was.
+	Unknown(Vec<u8>),
+}
+
+/// The data of an SVCB or HTTPS record, which share a format.
+/// https://tools.ietf.org/html/rfc9460#section-2.2
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Svcb {
+	pub priority: u16,
+	pub target: Name,
+	/// Parameters as (SvcParamKey, SvcParamValue), in the order they appeared.
+	pub params: Vec<(u16, Vec<u8>)>,
+}
+
+impl Svcb {
+	fn decode(r: &mut Reader, end: usize) -> Result<Svcb> {
+		let priority = r.read_u16()?;
+		let target = r.read_name()?;
+		let mut params = Vec::new();
+		while r.position() < end {
+			let key = r.read_u16()?;
+			let len = r.read_u16()?;
+			params.push((key, r.read_bytes(len as usize)?.to_vec()));
+		}
+		Ok(Svcb {
+			priority,
+			target,
+			params,
+		})
+	}
+
+	fn encode(&self, w: &mut Writer) -> Result<()> {
+		w.write_u16(self.priority);
+		w.write_name_uncompressed(&self.target);
+		for (key, value) in &self.params {
+			if value.len() > 0xffff {
+				return Err(Error::BadRdLength);
+			}
+			w.write_u16(*key);
+			w.write_u16(value.len() as u16);
+			w.write_bytes(value);
+		}
+		Ok(())
+	}
+}
+
+/// https://tools.ietf.org/html/rfc9460#section-14.3.2
+const SVC_MANDATORY: u16 = 0;
+const SVC_ALPN: u16 = 1;
+const SVC_NO_DEFAULT_ALPN: u16 = 2;
+const SVC_PORT: u16 = 3;
+const SVC_IPV4HINT: u16 = 4;
+const SVC_ECH: u16 = 5;
+const SVC_IPV6HINT: u16 = 6;
+
+fn svc_key_name(key: u16) -> String {
+	match key {
+		SVC_MANDATORY => "mandatory".to_string(),
+		SVC_ALPN => "alpn".to_string(),
+		SVC_NO_DEFAULT_ALPN => "no-default-alpn".to_string(),
+		SVC_PORT => "port".to_string(),
+		SVC_IPV4HINT => "ipv4hint".to_string(),
+		SVC_ECH => "ech".to_string(),
+		SVC_IPV6HINT => "ipv6hint".to_string(),
+		_ => format!("key{}", key),
+	}
+}
+
+/// https://tools.ietf.org/html/rfc9460#section-2.1
+impl fmt::Display for Svcb {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} {}", self.priority, self.target)?;
+		for (key, value) in &self.params {
+			write!(f, " {}", svc_key_name(*key))?;
+			if !fmt_svc_value(f, *key, value)? && !value.is_empty() {
+				write!(f, "=")?;
+				fmt_character_string(f, value)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Write `=value` for the keys with a special presentation format. Returns
+/// false if the key isn't one of those or its value is malformed, in which case
+/// nothing is written.
+fn fmt_svc_value(
+	f: &mut fmt::Formatter,
+	key: u16,
+	value: &[u8],
+) -> std::result::Result<bool, fmt::Error> {
+	let list: Vec<String> = match key {
+		SVC_MANDATORY if value.len().is_multiple_of(2) => value
+			.chunks(2)
+			.map(|k| svc_key_name(u16::from_be_bytes([k[0], k[1]])))
+			.collect(),
+		SVC_ALPN => {
+			let mut ids = Vec::new();
+			let mut rest = value;
+			while let Some((&len, tail)) = rest.split_first() {
+				if tail.len() < len as usize {
+					return Ok(false);
+				}
+				let (id, tail) = tail.split_at(len as usize);
+				let mut s = String::new();
+				for &b in id {
+					match b {
+						// Escaped once as part of a list, and again as part of a
+						// character-string.
+						b',' => s.push_str("\\\\,"),
+						b'\\' => s.push_str("\\\\\\\\"),
+						b'"' => s.push_str("\\\""),
+						0x20..=0x7e => s.push(b as char),
+						_ => s.push_str(&format!("\\{:03}", b)),
+					}
+				}
+				ids.push(s);
+				rest = tail;
+			}
+			return write!(f, "=\"{}\"", ids.join(",")).map(|_| true);
+		}
+		SVC_PORT if value.len() == 2 => vec![u16::from_be_bytes([value[0], value[1]]).to_string()],
+		SVC_IPV4HINT if !value.is_empty() && value.len().is_multiple_of(4) => value
+			.chunks(4)
+			.map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3]).to_string())
+			.collect(),
+		SVC_ECH => vec![base64(value)],
+		SVC_IPV6HINT if !value.is_empty() && value.len().is_multiple_of(16) => value
+			.chunks(16)
+			.map(|a| {
+				let mut octets = [0; 16];
+				octets.copy_from_slice(a);
+				Ipv6Addr::from(octets).to_string()
+			})
+			.collect(),
+		_ => return Ok(false),
+	};
+	write!(f, "={}", list.join(",")).map(|_| true)
+}
+
+/// https://tools.ietf.org/html/rfc4648#section-4
+fn base64(data: &[u8]) -> String {
+	const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut s = String::new();
+	for chunk in data.chunks(3) {
+		let b = [
+			chunk[0],
+			*chunk.get(1).unwrap_or(&0),
+			*chunk.get(2).unwrap_or(&0),
+		];
+		let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+		for i in 0..4 {
+			if i <= chunk.len() {
+				s.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+			} else {
+				s.push('=');
+			}
+		}
+	}
+	s
+}
+
+impl RData {
+	/// Decode `len` bytes of data for a record of type `rtype`.
+	pub fn decode(rtype: Type, r: &mut Reader, len: usize) -> Result<RData> {
+		let end = r.position() + len;
+		let rdata = match rtype {
+			Type::A => RData::A(read_ipv4(r)?),
+			Type::NS => RData::NS(r.read_name()?),
+			Type::MD => RData::MD(r.read_name()?),
+			Type::MF => RData::MF(r.read_name()?),
+			Type::CNAME => RData::CNAME(r.read_name()?),
+			Type::SOA => RData::SOA {
+				mname: r.read_name()?,
+				rname: r.read_name()?,
+				serial: r.read_u32()?,
+				refresh: r.read_u32()?,
+				retry: r.read_u32()?,
+				expire: r.read_u32()?,
+				minimum: r.read_u32()?,
+			},
+			Type::MB => RData::MB(r.read_name()?),
+			Type::MG => RData::MG(r.read_name()?),
+			Type::MR => RData::MR(r.read_name()?),
+			Type::NULL => RData::NULL(r.read_bytes(len)?.to_vec()),
+			Type::WKS => {
+				let address = read_ipv4(r)?;
+				let protocol = r.read_u8()?;
+				RData::WKS {
+					address,
+					protocol,
+					bitmap: read_rest(r, end)?,
+				}
+			}
+			Type::PTR => RData::PTR(r.read_name()?),
+			Type::HINFO => RData::HINFO {
+				cpu: r.read_character_string()?,
+				os: r.read_character_string()?,
+			},
+			Type::MINFO => RData::MINFO {
+				rmailbx: r.read_name()?,
+				emailbx: r.read_name()?,
+			},
+			Type::MX => RData::MX {
+				preference: r.read_u16()?,
+				exchange: r.read_name()?,
+			},
+			Type::TXT => {
+				let mut strings = Vec::new();
+				while r.position() < end {
+					strings.push(r.read_character_string()?);
+				}
+				RData::TXT(strings)
+			}
+			Type::AAAA => {
+				let mut octets = [0; 16];
+				octets.copy_from_slice(r.read_bytes(16)?);
+				RData::AAAA(Ipv6Addr::from(octets))
+			}
+			Type::SRV => RData::SRV {
+				priority: r.read_u16()?,
+				weight: r.read_u16()?,
+				port: r.read_u16()?,
+				target: r.read_name()?,
+			},
+			Type::NAPTR => RData::NAPTR {
+				order: r.read_u16()?,
+				preference: r.read_u16()?,
+				flags: r.read_character_string()?,
+				services: r.read_character_string()?,
+				regexp: r.read_character_string()?,
+				replacement: r.read_name()?,
+			},
+			Type::DNAME => RData::DNAME(r.read_name()?),
+			Type::OPT => RData::OPT(EdnsOption::decode_all(r, len)?),
+			Type::SSHFP => RData::SSHFP {
+				algorithm: r.read_u8()?,
+				fingerprint_type: r.read_u8()?,
+				fingerprint: read_rest(r, end)?,
+			},
+			Type::TLSA => RData::TLSA {
+				usage: r.read_u8()?,
+				selector: r.read_u8()?,
+				matching_type: r.read_u8()?,
+				data: read_rest(r, end)?,
+			},
+			Type::SVCB => RData::SVCB(Svcb::decode(r, end)?),
+			Type::HTTPS => RData::HTTPS(Svcb::decode(r, end)?),
+			Type::URI => RData::URI {
+				priority: r.read_u16()?,
+				weight: r.read_u16()?,
+				target: read_rest(r, end)?,
+			},
+			Type::CAA => RData::CAA {
+				flags: r.read_u8()?,
+				tag: r.read_character_string()?,
+				value: read_rest(r, end)?,
+			},
+			Type::ANY | Type::Unknown(_) => RData::Unknown(r.read_bytes(len)?.to_vec()),
+		};
+
+		if r.position() != end {
+			return Err(Error::BadRdLength);
+		}
+		Ok(rdata)
+	}
+
+	/// The type of record this data belongs in.
+	pub fn rtype(&self) -> Option<Type> {
+		Some(match self {
+			RData::A(_) => Type::A,
+			RData::NS(_) => Type::NS,
+			RData::MD(_) => Type::MD,
+			RData::MF(_) => Type::MF,
+			RData::CNAME(_) => Type::CNAME,
+			RData::SOA { .. } => Type::SOA,
+			RData::MB(_) => Type::MB,
+			RData::MG(_) => Type::MG,
+			RData::MR(_) => Type::MR,
+			RData::NULL(_) => Type::NULL,
+			RData::WKS { .. } => Type::WKS,
+			RData::PTR(_) => Type::PTR,
+			RData::HINFO { .. } => Type::HINFO,
+			RData::MINFO { .. } => Type::MINFO,
+			RData::MX { .. } => Type::MX,
+			RData::TXT(_) => Type::TXT,
+			RData::AAAA(_) => Type::AAAA,
+			RData::SRV { .. } => Type::SRV,
+			RData::NAPTR { .. } => Type::NAPTR,
+			RData::DNAME(_) => Type::DNAME,
+			RData::OPT(_) => Type::OPT,
+			RData::SSHFP { .. } => Type::SSHFP,
+			RData::TLSA { .. } => Type::TLSA,
+			RData::SVCB(_) => Type::SVCB,
+			RData::HTTPS(_) => Type::HTTPS,
+			RData::URI { .. } => Type::URI,
+			RData::CAA { .. } => Type::CAA,
+			RData::Unknown(_) => return None,
+		})
+	}
+
+	/// Encode the data, without its length. Names are only compressed in the
+	/// types defined by RFC 1035.
+	/// https://tools.ietf.org/html/rfc3597#section-4
+	pub fn encode(&self, w: &mut Writer) -> Result<()> {
+		match self {
+			RData::A(address) => w.write_bytes(&address.octets()),
+			RData::NS(name)
+			| RData::MD(name)
+			| RData::MF(name)
+			| RData::CNAME(name)
+			| RData::MB(name)
+			| RData::MG(name)
+			| RData::MR(name)
+			| RData::PTR(name) => w.write_name(name),
+			RData::SOA {
+				mname,
+				rname,
+				serial,
+				refresh,
+				retry,
+				expire,
+				minimum,
+			} => {
+				w.write_name(mname);
+				w.write_name(rname);
+				for x in &[serial, refresh, retry, expire, minimum] {
+					w.write_u32(**x);
+				}
+			}
+			RData::NULL(data) | RData::Unknown(data) => w.write_bytes(data),
+			RData::WKS {
+				address,
+				protocol,
+				bitmap,
+			} => {
+				w.write_bytes(&address.octets());
+				w.write_u8(*protocol);
+				w.write_bytes(bitmap);
+			}
+			RData::HINFO { cpu, os } => {
+				w.write_character_string(cpu)?;
+				w.write_character_string(os)?;
+			}
+			RData::MINFO { rmailbx, emailbx } => {
+				w.write_name(rmailbx);
+				w.write_name(emailbx);
+			}
+			RData::MX {
+				preference,
+				exchange,
+			} => {
+				w.write_u16(*preference);
+				w.write_name(exchange);
+			}
+			RData::TXT(strings) => {
+				for s in strings {
+					w.write_character_string(s)?;
+				}
+			}
+			RData::AAAA(address) => w.write_bytes(&address.octets()),
+			RData::SRV {
+				priority,
+				weight,
+				port,
+				target,
+			} => {
+				w.write_u16(*priority);
+				w.write_u16(*weight);
+				w.write_u16(*port);
+				w.write_name_uncompressed(target);
+			}
+			RData::NAPTR {
+				order,
+				preference,
+				flags,
+				services,
+				regexp,
+				replacement,
+			} => {
+				w.write_u16(*order);
+				w.write_u16(*preference);
+				w.write_character_string(flags)?;
+				w.write_character_string(services)?;
+				w.write_character_string(regexp)?;
+				w.write_name_uncompressed(replacement);
+			}
+			// https://tools.ietf.org/html/rfc6672#section-2.5
+			RData::DNAME(target) => w.write_name_uncompressed(target),
+			RData::OPT(options) => {
+				for option in options {
+					option.encode(w)?;
+				}
+			}
+			RData::SSHFP {
+				algorithm,
+				fingerprint_type,
+				fingerprint,
+			} => {
+				w.write_u8(*algorithm);
+				w.write_u8(*fingerprint_type);
+				w.write_bytes(fingerprint);
+			}
+			RData::TLSA {
+				usage,
+				selector,
+				matching_type,
+				data,
+			} => {
+				w.write_u8(*usage);
+				w.write_u8(*selector);
+				w.write_u8(*matching_type);
+				w.write_bytes(data);
+			}
+			RData::SVCB(svcb) | RData::HTTPS(svcb) => svcb.encode(w)?,
+			RData::URI {
+				priority,
+				weight,
+				target,
+			} => {
+				w.write_u16(*priority);
+				w.write_u16(*weight);
+				w.write_bytes(target);
+			}
+			RData::CAA { flags, tag, value } => {
+				w.write_u8(*flags);
+				w.write_character_string(tag)?;
+				w.write_bytes(value);
+			}
+		}
+		Ok(())
+	}
+}
+
+fn read_ipv4(r: &mut Reader) -> Result<Ipv4Addr> {
+	let b = r.read_bytes(4)?;
+	Ok(Ipv4Addr::new(b[0], b[1], b[2], b[3]))
+}
+
+/// Read everything up to the end of the record data.
+fn read_rest(r: &mut Reader, end: usize) -> Result<Vec<u8>> {
+	if r.position() > end {
+		return Err(Error::BadRdLength);
+	}
+	Ok(r.read_bytes(end - r.position())?.to_vec())
+}
+
+/// Presentation format, as used in zone files.
+/// https://tools.ietf.org/html/rfc1035#section-5.1
+impl fmt::Display for RData {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			RData::A(address) => write!(f, "{}", address),
+			RData::NS(name)
+			| RData::MD(name)
+			| RData::MF(name)
+			| RData::CNAME(name)
+			| RData::MB(name)
+			| RData::MG(name)
+			| RData::MR(name)
+			| RData::PTR(name)
+			| RData::DNAME(name) => write!(f, "{}", name),
+			RData::SOA {
+				mname,
+				rname,
+				serial,
+				refresh,
+				retry,
+				expire,
+				minimum,
+			} => write!(
+				f,
+				"{} {} {} {} {} {} {}",
+				mname, rname, serial, refresh, retry, expire, minimum
+			),
+			RData::NULL(data) | RData::Unknown(data) => fmt_unknown(f, data),
+			RData::WKS {
+				address,
+				protocol,
+				bitmap,
+			} => {
+				write!(f, "{} {}", address, protocol)?;
+				for (i, byte) in bitmap.iter().enumerate() {
+					for bit in 0..8 {
+						if byte & (0x80 >> bit) != 0 {
+							write!(f, " {}", i * 8 + bit)?;
+						}
+					}
+				}
+				Ok(())
+			}
+			RData::HINFO { cpu, os } => {
+				fmt_character_string(f, cpu)?;
+				write!(f, " ")?;
+				fmt_character_string(f, os)
+			}
+			RData::MINFO { rmailbx, emailbx } => write!(f, "{} {}", rmailbx, emailbx),
+			RData::MX {
+				preference,
+				exchange,
+			} => write!(f, "{} {}", preference, exchange),
+			RData::TXT(strings) => {
+				for (i, s) in strings.iter().enumerate() {
+					if i > 0 {
+						write!(f, " ")?;
+					}
+					fmt_character_string(f, s)?;
+				}
+				Ok(())
+			}
+			RData::AAAA(address) => write!(f, "{}", address),
+			RData::SRV {
+				priority,
+				weight,
+				port,
+				target,
+			} => write!(f, "{} {} {} {}", priority, weight, port, target),
+			RData::NAPTR {
+				order,
+				preference,
+				flags,
+				services,
+				regexp,
+				replacement,
+			} => {
+				write!(f, "{} {} ", order, preference)?;
+				for s in &[flags, services, regexp] {
+					fmt_character_string(f, s)?;
+					write!(f, " ")?;
+				}
+				write!(f, "{}", replacement)
+			}
+			RData::OPT(options) => {
+				for (i, option) in options.iter().enumerate() {
+					if i > 0 {
+						write!(f, "; ")?;
+					}
+					write!(f, "{}", option)?;
+				}
+				Ok(())
+			}
+			RData::SSHFP {
+				algorithm,
+				fingerprint_type,
+				fingerprint,
+			} => {
+				write!(f, "{} {} {}", algorithm, fingerprint_type, Hex(fingerprint))
+			}
+			RData::TLSA {
+				usage,
+				selector,
+				matching_type,
+				data,
+			} => {
+				write!(f, "{} {} {} {}", usage, selector, matching_type, Hex(data))
+			}
+			RData::SVCB(svcb) | RData::HTTPS(svcb) => write!(f, "{}", svcb),
+			RData::URI {
+				priority,
+				weight,
+				target,
+			} => {
+				write!(f, "{} {} ", priority, weight)?;
+				fmt_character_string(f, target)
+			}
+			RData::CAA { flags, tag, value } => {
+				write!(f, "{} {} ", flags, String::from_utf8_lossy(tag))?;
+				fmt_character_string(f, value)
+			}
+		}
+	}
+}
+
+/// Bytes shown as uppercase hex digits, as in presentation format.
+pub(crate) struct Hex<'a>(pub &'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		for b in self.0 {
+			write!(f, "{:02X}", b)?;
+		}
+		Ok(())
+	}
+}
+
+/// The generic encoding for data we can't otherwise display.
+/// https://tools.ietf.org/html/rfc3597#section-5
+fn fmt_unknown(f: &mut fmt::Formatter, data: &[u8]) -> fmt::Result {
+	write!(f, "\\# {}", data.len())?;
+	if !data.is_empty() {
+		write!(f, " {}", Hex(data))?;
+	}
+	Ok(())
+}
+
+fn fmt_character_string(f: &mut fmt::Formatter, s: &[u8]) -> fmt::Result {
+	write!(f, "\"")?;
+	for &b in s {
+		match b {
+			b'"' | b'\\' => write!(f, "\\{}", b as char)?,
+			0x20..=0x7e => write!(f, "{}", b as char)?,
+			_ => write!(f, "\\{:03}", b)?,
+		}
+	}
+	write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn name(s: &str) -> Name {
+		s.parse().unwrap()
+	}
+
+	/// Encode `rdata`, decode it again as `rtype`, and check that it comes
+	/// back the same and is shown as `text`.
+	fn check(rtype: Type, rdata: RData, text: &str) {
+		let mut w = Writer::new();
+		rdata.encode(&mut w).unwrap();
+		let data = w.into_bytes();
+		let decoded = RData::decode(rtype, &mut Reader::new(&data), data.len()).unwrap();
+		assert_eq!(decoded, rdata, "{}", text);
+		assert_eq!(rdata.to_string(), text);
+		assert_eq!(rdata.rtype().unwrap_or(rtype), rtype);
+	}
+
+	#[test]
+	fn rfc1035_types_round_trip() {
+		let cases = vec![
+			(Type::A, RData::A(Ipv4Addr::new(192, 0, 2, 1)), "192.0.2.1"),
+			(Type::NS, RData::NS(name("ns.example")), "ns.example."),
+			(Type::MD, RData::MD(name("md.example")), "md.example."),
+			(Type::MF, RData::MF(name("mf.example")), "mf.example."),
+			(
+				Type::CNAME,
+				RData::CNAME(name("www.example")),
+				"www.example.",
+			),
+			(
+				Type::SOA,
+				RData::SOA {
+					mname: name("ns.example"),
+					rname: name("admin.example"),
+					serial: 2024010101,
+					refresh: 7200,
+					retry: 3600,
+					expire: 1209600,
+					minimum: 300,
+				},
+				"ns.example. admin.example. 2024010101 7200 3600 1209600 300",
+			),
+			(Type::MB, RData::MB(name("mb.example")), "mb.example."),
+			(Type::MG, RData::MG(name("mg.example")), "mg.example."),
+			(Type::MR, RData::MR(name("mr.example")), "mr.example."),
+			(Type::NULL, RData::NULL(vec![0xde, 0xad]), "\\# 2 DEAD"),
+			(
+				Type::WKS,
+				RData::WKS {
+					address: Ipv4Addr::new(192, 0, 2, 1),
+					protocol: 6,
+					bitmap: vec![
+						0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80,
+					],
+				},
+				"192.0.2.1 6 25 80",
+			),
+			(Type::PTR, RData::PTR(name("host.example")), "host.example."),
+			(
+				Type::HINFO,
+				RData::HINFO {
+					cpu: b"x86 \"64\"".to_vec(),
+					os: b"Linux\\GNU".to_vec(),
+				},
+				"\"x86 \\\"64\\\"\" \"Linux\\\\GNU\"",
+			),
+			(
+				Type::MINFO,
+				RData::MINFO {
+					rmailbx: name("admin.example"),
+					emailbx: name("errors.example"),
+				},
+				"admin.example. errors.example.",
+			),
+			(
+				Type::MX,
+				RData::MX {
+					preference: 10,
+					exchange: name("mail.example"),
+				},
+				"10 mail.example.",
+			),
+			(
+				Type::TXT,
+				RData::TXT(vec![b"v=spf1 -all".to_vec(), vec![0, 0xff], Vec::new()]),
+				"\"v=spf1 -all\" \"\\000\\255\" \"\"",
+			),
+		];
+		for (rtype, rdata, text) in cases {
+			check(rtype, rdata, text);
+		}
+	}
+
+	#[test]
+	fn later_types_round_trip() {
+		let svcb = Svcb {
+			priority: 1,
+			target: name("svc.example"),
+			params: vec![
+				(SVC_MANDATORY, vec![0, 1]),
+				(SVC_ALPN, b"\x02h2\x03a,b\x03c\\d".to_vec()),
+				(SVC_NO_DEFAULT_ALPN, Vec::new()),
+				(SVC_PORT, vec![1, 187]),
+				(SVC_IPV4HINT, vec![192, 0, 2, 1, 192, 0, 2, 2]),
+				(SVC_ECH, vec![1, 2, 3]),
+				(SVC_IPV6HINT, Ipv6Addr::LOCALHOST.octets().to_vec()),
+				(65000, b"x y".to_vec()),
+			],
+		};
+		let svcb_text = r#"1 svc.example. mandatory=alpn alpn="h2,a\\,b,c\\\\d" no-default-alpn port=443 ipv4hint=192.0.2.1,192.0.2.2 ech=AQID ipv6hint=::1 key65000="x y""#;
+		let cases = vec![
+			(
+				Type::AAAA,
+				RData::AAAA("2001:db8::1".parse().unwrap()),
+				"2001:db8::1".to_string(),
+			),
+			(
+				Type::SRV,
+				RData::SRV {
+					priority: 0,
+					weight: 5,
+					port: 5060,
+					target: name("sip.example"),
+				},
+				"0 5 5060 sip.example.".to_string(),
+			),
+			(
+				Type::NAPTR,
+				RData::NAPTR {
+					order: 100,
+					preference: 10,
+					flags: b"u".to_vec(),
+					services: b"E2U+sip".to_vec(),
+					regexp: b"!^.*$!sip:info@example.com!".to_vec(),
+					replacement: Name::root(),
+				},
+				"100 10 \"u\" \"E2U+sip\" \"!^.*$!sip:info@example.com!\" .".to_string(),
+			),
+			(
+				Type::DNAME,
+				RData::DNAME(name("other.example")),
+				"other.example.".to_string(),
+			),
+			(
+				Type::OPT,
+				RData::OPT(vec![
+					EdnsOption::Nsid(b"ns1".to_vec()),
+					EdnsOption::Unknown(65001, vec![0xab]),
+				]),
+				"NSID: 6E7331 (\"ns1\"); OPT=65001: AB".to_string(),
+			),
+			(
+				Type::SSHFP,
+				RData::SSHFP {
+					algorithm: 4,
+					fingerprint_type: 2,
+					fingerprint: vec![0x12, 0xab, 0xcd],
+				},
+				"4 2 12ABCD".to_string(),
+			),
+			(
+				Type::TLSA,
+				RData::TLSA {
+					usage: 3,
+					selector: 1,
+					matching_type: 1,
+					data: vec![0x0d, 0x6f],
+				},
+				"3 1 1 0D6F".to_string(),
+			),
+			(Type::SVCB, RData::SVCB(svcb.clone()), svcb_text.to_string()),
+			(Type::HTTPS, RData::HTTPS(svcb), svcb_text.to_string()),
+			(
+				Type::URI,
+				RData::URI {
+					priority: 10,
+					weight: 1,
+					target: b"ftp://ftp1.example.com/public".to_vec(),
+				},
+				"10 1 \"ftp://ftp1.example.com/public\"".to_string(),
+			),
+			(
+				Type::CAA,
+				RData::CAA {
+					flags: 0,
+					tag: b"issue".to_vec(),
+					value: b"ca.example; \"x\"".to_vec(),
+				},
+				"0 issue \"ca.example; \\\"x\\\"\"".to_string(),
+			),
+			(
+				Type::Unknown(65280),
+				RData::Unknown(vec![0x0a, 0x00, 0x00, 0x01]),
+				"\\# 4 0A000001".to_string(),
+			),
+			(
+				Type::Unknown(65280),
+				RData::Unknown(Vec::new()),
+				"\\# 0".to_string(),
+			),
+		];
+		for (rtype, rdata, text) in cases {
+			check(rtype, rdata, &text);
+		}
+	}
+
+	#[test]
+	fn rejects_data_of_the_wrong_length() {
+		let data = [192, 0, 2, 1, 0];
+		assert!(matches!(
+			RData::decode(Type::A, &mut Reader::new(&data), data.len()),
+			Err(Error::BadRdLength)
+		));
+		assert!(matches!(
+			RData::decode(Type::MX, &mut Reader::new(&data[..1]), 1),
+			Err(Error::Truncated)
+		));
+	}
+}
@@ -0,0 +1,880 @@
+//! Resolving names by following referrals down from the root servers,
+//! instead of asking a recursive server to do it
+//! https://tools.ietf.org/html/rfc1034#section-5.3.3
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::{follow_cnames, Lookup};
+use crate::client::{Client, ResolverConfig, Response, PORT};
+use crate::edns;
+use crate::error::{Error, Result};
+use crate::name::Name;
+use crate::records::{Class, RData, Type};
+use crate::wire::{DnsHeaderFlags, Message, Question, Rcode, ResourceRecord};
+
+/// How many responses a lookup can go through before giving up, counting
+/// each referral and each restart from the root after a CNAME.
+const MAX_REFERRALS: usize = 32;
+
+/// How deep lookups of name server addresses can nest inside each other.
+const MAX_DEPTH: usize = 4;
+
+/// How many CNAMEs a lookup can follow.
+const MAX_CNAMES: usize = 16;
+
+/// https://www.iana.org/domains/root/servers
+const ROOT_SERVERS: [(&str, Ipv4Addr, Ipv6Addr); 13] = [
+	(
+		"a.root-servers.net",
+		Ipv4Addr::new(198, 41, 0, 4),
+		Ipv6Addr::new(0x2001, 0x503, 0xba3e, 0, 0, 0, 0x2, 0x30),
+	),
+	(
+		"b.root-servers.net",
+		Ipv4Addr::new(170, 247, 170, 2),
+		Ipv6Addr::new(0x2801, 0x1b8, 0x10, 0, 0, 0, 0, 0xb),
+	),
+	(
+		"c.root-servers.net",
+		Ipv4Addr::new(192, 33, 4, 12),
+		Ipv6Addr::new(0x2001, 0x500, 0x2, 0, 0, 0, 0, 0xc),
+	),
+	(
+		"d.root-servers.net",
+		Ipv4Addr::new(199, 7, 91, 13),
+		Ipv6Addr::new(0x2001, 0x500, 0x2d, 0, 0, 0, 0, 0xd),
+	),
+	(
+		"e.root-servers.net",
+		Ipv4Addr::new(192, 203, 230, 10),
+		Ipv6Addr::new(0x2001, 0x500, 0xa8, 0, 0, 0, 0, 0xe),
+	),
+	(
+		"f.root-servers.net",
+		Ipv4Addr::new(192, 5, 5, 241),
+		Ipv6Addr::new(0x2001, 0x500, 0x2f, 0, 0, 0, 0, 0xf),
+	),
+	(
+		"g.root-servers.net",
+		Ipv4Addr::new(192, 112, 36, 4),
+		Ipv6Addr::new(0x2001, 0x500, 0x12, 0, 0, 0, 0, 0xd0d),
+	),
+	(
+		"h.root-servers.net",
+		Ipv4Addr::new(198, 97, 190, 53),
+		Ipv6Addr::new(0x2001, 0x500, 0x1, 0, 0, 0, 0, 0x53),
+	),
+	(
+		"i.root-servers.net",
+		Ipv4Addr::new(192, 36, 148, 17),
+		Ipv6Addr::new(0x2001, 0x7fe, 0, 0, 0, 0, 0, 0x53),
+	),
+	(
+		"j.root-servers.net",
+		Ipv4Addr::new(192, 58, 128, 30),
+		Ipv6Addr::new(0x2001, 0x503, 0xc27, 0, 0, 0, 0x2, 0x30),
+	),
+	(
+		"k.root-servers.net",
+		Ipv4Addr::new(193, 0, 14, 129),
+		Ipv6Addr::new(0x2001, 0x7fd, 0, 0, 0, 0, 0, 0x1),
+	),
+	(
+		"l.root-servers.net",
+		Ipv4Addr::new(199, 7, 83, 42),
+		Ipv6Addr::new(0x2001, 0x500, 0x9f, 0, 0, 0, 0, 0x42),
+	),
+	(
+		"m.root-servers.net",
+		Ipv4Addr::new(202, 12, 27, 33),
+		Ipv6Addr::new(0x2001, 0xdc3, 0, 0, 0, 0, 0, 0x35),
+	),
+];
+
+/// A server for a zone, and whatever addresses we know it by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NameServer {
+	pub name: Name,
+	/// Empty until looked up, if the referral didn't come with glue.
+	pub addrs: Vec<IpAddr>,
+}
+
+/// The built in list of root servers.
+pub fn root_hints() -> Vec<NameServer> {
+	ROOT_SERVERS
+		.iter()
+		.map(|(name, v4, v6)| NameServer {
+			name: name.parse().unwrap(),
+			addrs: vec![IpAddr::V4(*v4), IpAddr::V6(*v6)],
+		})
+		.collect()
+}
+
+/// Where to start, and how to talk to each server on the way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IterativeConfig {
+	/// The servers to start from. Normally the root servers, but any set of
+	/// servers that are authoritative for the root will do.
+	pub root_hints: Vec<NameServer>,
+	/// Ask the hints for the current list of root servers before the first
+	/// lookup, rather than trusting the list to be up to date.
+	/// https://tools.ietf.org/html/rfc8109
+	pub prime: bool,
+	/// The port every server is sent queries on.
+	pub port: u16,
+	/// How long to wait for each server before trying the next one.
+	pub timeout: Duration,
+	/// How many times to try each server.
+	pub attempts: u32,
+	/// The UDP payload size to advertise with EDNS, or `None` to not use EDNS.
+	pub edns_udp_size: Option<u16>,
+}
+
+impl Default for IterativeConfig {
+	fn default() -> IterativeConfig {
+		IterativeConfig {
+			root_hints: root_hints(),
+			prime: true,
+			port: PORT,
+			timeout: Duration::from_secs(2),
+			attempts: 1,
+			edns_udp_size: Some(edns::DEFAULT_UDP_PAYLOAD_SIZE),
+		}
+	}
+}
+
+/// A response received while resolving iteratively, or why there wasn't one.
+#[derive(Debug)]
+pub struct Step<'a> {
+	/// The zone the server was asked about, as one of its servers.
+	pub zone: &'a Name,
+	/// The server that was asked.
+	pub server: &'a Name,
+	pub addr: SocketAddr,
+	pub response: std::result::Result<&'a Response, &'a Error>,
+	/// How many lookups of name server addresses this is nested inside.
+	pub depth: usize,
+}
+
+/// Looks up records by asking the authoritative servers for each zone in
+/// turn, starting at the root.
+#[derive(Debug)]
+pub struct IterativeResolver {
+	config: IterativeConfig,
+	/// The root servers found by priming, once that has been done.
+	roots: Mutex<Option<Vec<NameServer>>>,
+}
+
+impl IterativeResolver {
+	pub fn new(config: IterativeConfig) -> IterativeResolver {
+		IterativeResolver {
+			config,
+			roots: Mutex::new(None),
+		}
+	}
+
+	pub fn config(&self) -> &IterativeConfig {
+		&self.config
+	}
+
+	/// Look up the records of one type for a name.
+	pub fn lookup(&self, name: &Name, rtype: Type) -> Result<Lookup> {
+		self.resolve(name, rtype, 0, &mut |_| {})
+	}
+
+	/// Look up the records of one type for a name, calling `trace` with each
+	/// response on the way, including any from priming.
+	pub fn trace<F: FnMut(&Step)>(&self, name: &Name, rtype: Type, mut trace: F) -> Result<Lookup> {
+		self.resolve(name, rtype, 0, &mut trace)
+	}
+
+	/// The servers to start each lookup from. Until priming works the hints
+	/// are used, and it's tried again on the next lookup.
+	fn roots(&self, trace: &mut dyn FnMut(&Step)) -> Vec<NameServer> {
+		if !self.config.prime {
+			return self.config.root_hints.clone();
+		}
+		if let Some(roots) = self.roots.lock().unwrap().as_ref() {
+			return roots.clone();
+		}
+		// Other lookups shouldn't wait on our queries, so the lock isn't held
+		// while priming. If two lookups prime at once, the last to finish wins.
+		match self.prime(trace) {
+			Some(roots) => {
+				*self.roots.lock().unwrap() = Some(roots.clone());
+				roots
+			}
+			None => self.config.root_hints.clone(),
+		}
+	}
+
+	/// Ask the hints which servers are authoritative for the root.
+	/// https://tools.ietf.org/html/rfc8109#section-3
+	fn prime(&self, trace: &mut dyn FnMut(&Step)) -> Option<Vec<NameServer>> {
+		let root = Name::root();
+		for hint in &self.config.root_hints {
+			for &addr in &hint.addrs {
+				let result = self.query(addr, &root, Type::NS);
+				trace(&Step {
+					zone: &root,
+					server: &hint.name,
+					addr: SocketAddr::new(addr, self.config.port),
+					response: result.as_ref(),
+					depth: 0,
+				});
+				let message = match result {
+					Ok(response) => response.message,
+					Err(_) => continue,
+				};
+				let ns = message
+					.answers
+					.iter()
+					.filter(|rr| rr.name.is_root() && rr.rtype == Type::NS);
+				let servers = name_servers(ns, &message.additionals, &root);
+				if servers.iter().any(|ns| !ns.addrs.is_empty()) {
+					return Some(servers);
+				}
+			}
+		}
+		None
+	}
+
+	fn resolve(
+		&self,
+		name: &Name,
+		rtype: Type,
+		depth: usize,
+		trace: &mut dyn FnMut(&Step),
+	) -> Result<Lookup> {
+		if depth > MAX_DEPTH {
+			return Err(Error::TooManyReferrals);
+		}
+
+		let mut cnames = Vec::new();
+		let mut current = name.clone();
+		let mut zone = Name::root();
+		let mut servers = self.roots(trace);
+		for _ in 0..MAX_REFERRALS {
+			let mut message =
+				self.query_zone(&zone, &mut servers, &current, rtype, depth, trace)?;
+			// A server can only vouch for names in its own zone.
+			message.answers.retain(|rr| rr.name.ends_with(&zone));
+
+			let answer = follow_cnames(&message, &current, rtype);
+			if !answer.records.is_empty() {
+				cnames.extend(answer.cnames);
+				return Ok(Lookup {
+					name: name.clone(),
+					cnames,
+					records: answer.records,
+				});
+			}
+			let mut next = answer.canonical_name().clone();
+			cnames.extend(answer.cnames);
+			if let Some(cname) = synthesize_cname(&message, &next) {
+				if let RData::CNAME(target) = &cname.rdata {
+					next = target.clone();
+				}
+				cnames.push(cname);
+			}
+			if cnames.len() > MAX_CNAMES {
+				return Err(Error::CnameLoop);
+			}
+
+			if message.rcode() == Rcode::NXDomain {
+				return Err(Error::Server(Rcode::NXDomain));
+			}
+			if next != current {
+				// The target could be in any zone, so start again at the top.
+				current = next;
+				zone = Name::root();
+				servers = self.roots(trace);
+				continue;
+			}
+			if let Some((child, child_servers)) = referral(&message, &zone, &current) {
+				zone = child;
+				servers = child_servers;
+				continue;
+			}
+			// The name exists, but has no records of this type.
+			return Ok(Lookup {
+				name: name.clone(),
+				cnames,
+				records: Vec::new(),
+			});
+		}
+		Err(Error::TooManyReferrals)
+	}
+
+	/// Ask the servers for `zone` in turn until one gives a usable response,
+	/// looking up the addresses of any that came without glue.
+	fn query_zone(
+		&self,
+		zone: &Name,
+		servers: &mut [NameServer],
+		name: &Name,
+		rtype: Type,
+		depth: usize,
+		trace: &mut dyn FnMut(&Step),
+	) -> Result<Message> {
+		let mut lame = false;
+		let mut last_error = None;
+		for server in servers.iter_mut() {
+			if server.addrs.is_empty() {
+				// A server inside the zone can only be found by asking the
+				// zone, which is what we're trying to do.
+				if server.name.ends_with(zone) {
+					continue;
+				}
+				server.addrs = self.resolve_addrs(&server.name, depth + 1, trace);
+			}
+			for &addr in &server.addrs {
+				let result = self.query(addr, name, rtype);
+				trace(&Step {
+					zone,
+					server: &server.name,
+					addr: SocketAddr::new(addr, self.config.port),
+					response: result.as_ref(),
+					depth,
+				});
+				match result {
+					Ok(response) if usable(&response.message, zone, name) => {
+						return Ok(response.message)
+					}
+					Ok(_) => lame = true,
+					Err(e) => last_error = Some(e),
+				}
+			}
+		}
+		match last_error {
+			Some(e) if !lame => Err(e),
+			_ => Err(Error::LameDelegation(zone.clone())),
+		}
+	}
+
+	/// The addresses of a name server, found with lookups of their own.
+	fn resolve_addrs(
+		&self,
+		name: &Name,
+		depth: usize,
+		trace: &mut dyn FnMut(&Step),
+	) -> Vec<IpAddr> {
+		for &rtype in &[Type::A, Type::AAAA] {
+			if let Ok(lookup) = self.resolve(name, rtype, depth, trace) {
+				let addrs: Vec<IpAddr> = lookup.ip_addrs().collect();
+				if !addrs.is_empty() {
+					return addrs;
+				}
+			}
+		}
+		Vec::new()
+	}
+
+	/// Send a single non-recursive query to one server.
+	fn query(&self, addr: IpAddr, name: &Name, rtype: Type) -> Result<Response> {
+		let client = Client::new(ResolverConfig {
+			nameservers: vec![SocketAddr::new(addr, self.config.port)],
+			timeout: self.config.timeout,
+			attempts: self.config.attempts,
+			edns_udp_size: self.config.edns_udp_size,
+			..Default::default()
+		});
+		let request = Message {
+			questions: vec![Question {
+				name: name.clone(),
+				qtype: rtype,
+				qclass: Class::IN,
+			}],
+			..Default::default()
+		};
+		client.query(&request)
+	}
+}
+
+/// Whether a response from a server for `zone` can be used. It has to be an
+/// authoritative answer, or a referral further down towards `name`. Anything
+/// else means the server is lame or broken.
+fn usable(message: &Message, zone: &Name, name: &Name) -> bool {
+	match message.rcode() {
+		Rcode::NoError | Rcode::NXDomain => {}
+		_ => return false,
+	}
+	message
+		.header
+		.flags()
+		.contains(DnsHeaderFlags::AUTHORITATIVE)
+		|| referral(message, zone, name).is_some()
+}
+
+/// The zone and servers a response from a server for `zone` refers us to,
+/// if it's a referral to a zone below `zone` that contains `name`.
+/// https://tools.ietf.org/html/rfc1034#section-4.3.2
+fn referral(message: &Message, zone: &Name, name: &Name) -> Option<(Name, Vec<NameServer>)> {
+	let child = &message
+		.authorities
+		.iter()
+		.find(|rr| {
+			rr.rtype == Type::NS
+				&& rr.name != *zone
+				&& rr.name.ends_with(zone)
+				&& name.ends_with(&rr.name)
+		})?
+		.name;
+	let ns = message
+		.authorities
+		.iter()
+		.filter(|rr| rr.rtype == Type::NS && rr.name == *child);
+	let servers = name_servers(ns, &message.additionals, zone);
+	if servers.is_empty() {
+		return None;
+	}
+	Some((child.clone(), servers))
+}
+
+/// The servers named by some NS records, with addresses from any glue. Glue
+/// is only believed for names in `bailiwick`, the zone of the server that
+/// sent it, since it could say anything about names elsewhere.
+fn name_servers<'a, I>(ns: I, additionals: &[ResourceRecord], bailiwick: &Name) -> Vec<NameServer>
+where
+	I: Iterator<Item = &'a ResourceRecord>,
+{
+	let mut servers: Vec<NameServer> = ns
+		.filter_map(|rr| match &rr.rdata {
+			RData::NS(name) => Some(name.clone()),
+			_ => None,
+		})
+		.map(|name| {
+			let mut addrs: Vec<IpAddr> = if name.ends_with(bailiwick) {
+				additionals
+					.iter()
+					.filter(|rr| rr.name == name)
+					.filter_map(|rr| match rr.rdata {
+						RData::A(addr) => Some(IpAddr::V4(addr)),
+						RData::AAAA(addr) => Some(IpAddr::V6(addr)),
+						_ => None,
+					})
+					.collect()
+			} else {
+				Vec::new()
+			};
+			addrs.sort_by_key(IpAddr::is_ipv6);
+			NameServer { name, addrs }
+		})
+		.collect();
+	// Servers we can ask straight away go first.
+	servers.sort_by_key(|ns| ns.addrs.is_empty());
+	servers
+}
+
+/// The CNAME implied by a DNAME for one of the ancestors of `name` in the
+/// answer, for servers that don't include it themselves.
+/// https://tools.ietf.org/html/rfc6672#section-3.3
+fn synthesize_cname(message: &Message, name: &Name) -> Option<ResourceRecord> {
+	message.answers.iter().find_map(|rr| match &rr.rdata {
+		RData::DNAME(target) if name.ends_with(&rr.name) && *name != rr.name => {
+			let prefix = name.label_count() - rr.name.label_count();
+			let target = Name::from_labels(name.labels().take(prefix))
+				.ok()?
+				.concat(target)
+				.ok()?;
+			Some(ResourceRecord {
+				name: name.clone(),
+				rtype: Type::CNAME,
+				class: rr.class,
+				ttl: rr.ttl,
+				rdata: RData::CNAME(target),
+			})
+		}
+		_ => None,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::net::UdpSocket;
+	use std::thread;
+
+	/// How a stand-in server answers.
+	#[derive(Clone, Copy)]
+	enum Behaviour {
+		Authoritative,
+		/// Lame by refusing every query.
+		Refused,
+		/// Lame by answering without authority, and with no referral.
+		NotAuthoritative,
+	}
+
+	/// An authoritative server for some zones, answering on a loopback
+	/// address. DNAMEs are returned without a synthesized CNAME, to check
+	/// that the resolver makes its own.
+	struct StandIn {
+		behaviour: Behaviour,
+		zones: Vec<(Name, Vec<ResourceRecord>)>,
+	}
+
+	impl StandIn {
+		fn serve(self, socket: UdpSocket) {
+			thread::spawn(move || {
+				let mut buf = [0; 4096];
+				while let Ok((len, from)) = socket.recv_from(&mut buf) {
+					let request = Message::decode(&buf[..len]).unwrap();
+					let response = self.answer(&request).encode().unwrap();
+					socket.send_to(&response, from).unwrap();
+				}
+			});
+		}
+
+		fn answer(&self, request: &Message) -> Message {
+			let question = request.questions[0].clone();
+			let mut response = Message {
+				questions: vec![question.clone()],
+				..Default::default()
+			};
+			response.header.id = request.header.id;
+			let mut flags = DnsHeaderFlags::RESPONSE;
+			let zone = self
+				.zones
+				.iter()
+				.filter(|(apex, _)| question.name.ends_with(apex))
+				.max_by_key(|(apex, _)| apex.label_count());
+			let (apex, records) = match (self.behaviour, zone) {
+				(Behaviour::Authoritative, Some((apex, records))) => (apex, records),
+				(Behaviour::NotAuthoritative, _) => {
+					response.header.set_flags(flags);
+					return response;
+				}
+				_ => {
+					response.header.set_flags(flags);
+					response.header.set_rcode(Rcode::Refused);
+					return response;
+				}
+			};
+
+			let of_type = |owner: &Name, rtype: Type| {
+				records
+					.iter()
+					.filter(|rr| rr.name == *owner && rr.rtype == rtype)
+					.cloned()
+					.collect::<Vec<_>>()
+			};
+			let glue = |ns: &[ResourceRecord]| {
+				ns.iter()
+					.filter_map(|rr| match &rr.rdata {
+						RData::NS(target) => Some(of_type(target, Type::A)),
+						_ => None,
+					})
+					.flatten()
+					.collect::<Vec<_>>()
+			};
+
+			let mut target = question.name.clone();
+			loop {
+				let cut = records
+					.iter()
+					.filter(|rr| {
+						rr.rtype == Type::NS && rr.name != *apex && target.ends_with(&rr.name)
+					})
+					.map(|rr| &rr.name)
+					.max_by_key(|name| name.label_count());
+				if let Some(cut) = cut {
+					response.authorities = of_type(cut, Type::NS);
+					response.additionals = glue(&response.authorities);
+					break;
+				}
+
+				flags |= DnsHeaderFlags::AUTHORITATIVE;
+				let matching = of_type(&target, question.qtype);
+				if !matching.is_empty() {
+					if question.qtype == Type::NS {
+						response.additionals = glue(&matching);
+					}
+					response.answers.extend(matching);
+					break;
+				}
+				if let Some(rr) = of_type(&target, Type::CNAME).pop() {
+					response.answers.push(rr.clone());
+					if let RData::CNAME(alias) = rr.rdata {
+						target = alias;
+					}
+					if target.ends_with(apex) {
+						continue;
+					}
+					break;
+				}
+				let dname = records.iter().find(|rr| {
+					rr.rtype == Type::DNAME && target.ends_with(&rr.name) && target != rr.name
+				});
+				if let Some(rr) = dname {
+					response.answers.push(rr.clone());
+					break;
+				}
+				if response.answers.is_empty()
+					&& !records.iter().any(|rr| rr.name.ends_with(&target))
+				{
+					response.header.set_rcode(Rcode::NXDomain);
+				}
+				response.authorities = of_type(apex, Type::SOA);
+				break;
+			}
+			response.header.set_flags(flags);
+			response
+		}
+	}
+
+	fn name(s: &str) -> Name {
+		s.parse().unwrap()
+	}
+
+	fn rr(owner: &str, rdata: RData) -> ResourceRecord {
+		ResourceRecord {
+			name: name(owner),
+			rtype: rdata.rtype().unwrap(),
+			class: Class::IN,
+			ttl: 300,
+			rdata,
+		}
+	}
+
+	fn a(owner: &str, addr: &str) -> ResourceRecord {
+		rr(owner, RData::A(addr.parse().unwrap()))
+	}
+
+	fn ns(owner: &str, target: &str) -> ResourceRecord {
+		rr(owner, RData::NS(name(target)))
+	}
+
+	fn soa(zone: &str) -> ResourceRecord {
+		rr(
+			zone,
+			RData::SOA {
+				mname: name(zone),
+				rname: name(zone),
+				serial: 1,
+				refresh: 3600,
+				retry: 600,
+				expire: 86400,
+				minimum: 60,
+			},
+		)
+	}
+
+	fn zone(apex: &str, records: Vec<ResourceRecord>) -> (Name, Vec<ResourceRecord>) {
+		(name(apex), records)
+	}
+
+	fn authoritative(zones: Vec<(Name, Vec<ResourceRecord>)>) -> StandIn {
+		StandIn {
+			behaviour: Behaviour::Authoritative,
+			zones,
+		}
+	}
+
+	fn lame(behaviour: Behaviour) -> StandIn {
+		StandIn {
+			behaviour,
+			zones: Vec::new(),
+		}
+	}
+
+	/// Start the stand-ins on 127.0.0.20 and up, all on the same port, and a
+	/// resolver that starts from the first.
+	///
+	/// - The root delegates `test.` with glue and `example.` to a server in
+	///   `other.test.`, without glue.
+	/// - `test.` has CNAME and DNAME records pointing into `example.`, and
+	///   delegations to lame servers.
+	fn resolver() -> IterativeResolver {
+		let root = authoritative(vec![zone(
+			".",
+			vec![
+				soa("."),
+				ns(".", "a.root-servers.test."),
+				a("a.root-servers.test.", "127.0.0.20"),
+				ns("test.", "ns1.test."),
+				a("ns1.test.", "127.0.0.21"),
+				ns("example.", "ns.other.test."),
+			],
+		)]);
+		let test = authoritative(vec![zone(
+			"test.",
+			vec![
+				soa("test."),
+				ns("test.", "ns1.test."),
+				a("ns1.test.", "127.0.0.21"),
+				a("www.test.", "192.0.2.1"),
+				rr("alias.test.", RData::CNAME(name("www.example."))),
+				rr("d.test.", RData::DNAME(name("example."))),
+				ns("other.test.", "ns.other.test."),
+				a("ns.other.test.", "127.0.0.22"),
+				ns("broken.test.", "ns-refused.test."),
+				ns("broken.test.", "ns-noaa.test."),
+				ns("broken.test.", "ns.other.test."),
+				ns("dead.test.", "ns-refused.test."),
+				ns("dead.test.", "ns-noaa.test."),
+				a("ns-refused.test.", "127.0.0.23"),
+				a("ns-noaa.test.", "127.0.0.24"),
+			],
+		)]);
+		let other = authoritative(vec![
+			zone(
+				"other.test.",
+				vec![
+					soa("other.test."),
+					ns("other.test.", "ns.other.test."),
+					a("ns.other.test.", "127.0.0.22"),
+				],
+			),
+			zone(
+				"example.",
+				vec![
+					soa("example."),
+					ns("example.", "ns.other.test."),
+					a("www.example.", "192.0.2.2"),
+				],
+			),
+			zone(
+				"broken.test.",
+				vec![soa("broken.test."), a("host.broken.test.", "192.0.2.3")],
+			),
+		]);
+		let servers = vec![
+			root,
+			test,
+			other,
+			lame(Behaviour::Refused),
+			lame(Behaviour::NotAuthoritative),
+		];
+
+		let first = UdpSocket::bind("127.0.0.20:0").unwrap();
+		let port = first.local_addr().unwrap().port();
+		let mut sockets = vec![first];
+		for i in 1..servers.len() {
+			let addr = Ipv4Addr::new(127, 0, 0, 20 + i as u8);
+			sockets.push(UdpSocket::bind((addr, port)).unwrap());
+		}
+		for (server, socket) in servers.into_iter().zip(sockets) {
+			server.serve(socket);
+		}
+
+		IterativeResolver::new(IterativeConfig {
+			root_hints: vec![NameServer {
+				name: name("a.root-servers.test."),
+				addrs: vec!["127.0.0.20".parse().unwrap()],
+			}],
+			port,
+			timeout: Duration::from_millis(500),
+			..Default::default()
+		})
+	}
+
+	fn addrs(lookup: &Lookup) -> Vec<IpAddr> {
+		lookup.ip_addrs().collect()
+	}
+
+	#[test]
+	fn follows_referrals_with_glue() {
+		let lookup = resolver().lookup(&name("www.test"), Type::A).unwrap();
+		assert_eq!(addrs(&lookup), vec!["192.0.2.1".parse::<IpAddr>().unwrap()]);
+		assert!(lookup.cnames.is_empty());
+	}
+
+	#[test]
+	fn looks_up_name_servers_without_glue() {
+		let lookup = resolver().lookup(&name("www.example"), Type::A).unwrap();
+		assert_eq!(addrs(&lookup), vec!["192.0.2.2".parse::<IpAddr>().unwrap()]);
+	}
+
+	#[test]
+	fn restarts_at_cname_into_another_zone() {
+		let lookup = resolver().lookup(&name("alias.test"), Type::A).unwrap();
+		assert_eq!(
+			lookup.cnames,
+			vec![rr("alias.test.", RData::CNAME(name("www.example.")))]
+		);
+		assert_eq!(lookup.canonical_name(), &name("www.example"));
+		assert_eq!(addrs(&lookup), vec!["192.0.2.2".parse::<IpAddr>().unwrap()]);
+	}
+
+	#[test]
+	fn synthesizes_cname_from_dname() {
+		let lookup = resolver().lookup(&name("www.d.test"), Type::A).unwrap();
+		assert_eq!(
+			lookup.cnames,
+			vec![rr("www.d.test.", RData::CNAME(name("www.example.")))]
+		);
+		assert_eq!(addrs(&lookup), vec!["192.0.2.2".parse::<IpAddr>().unwrap()]);
+	}
+
+	#[test]
+	fn skips_lame_servers() {
+		let resolver = resolver();
+		let lookup = resolver.lookup(&name("host.broken.test"), Type::A).unwrap();
+		assert_eq!(addrs(&lookup), vec!["192.0.2.3".parse::<IpAddr>().unwrap()]);
+
+		let mut lame = Vec::new();
+		resolver
+			.trace(&name("host.broken.test"), Type::A, |step| {
+				if let Ok(response) = step.response {
+					if !usable(&response.message, step.zone, &name("host.broken.test")) {
+						lame.push(step.server.clone());
+					}
+				}
+			})
+			.unwrap();
+		assert_eq!(lame, vec![name("ns-refused.test"), name("ns-noaa.test")]);
+	}
+
+	#[test]
+	fn fails_when_every_server_is_lame() {
+		match resolver().lookup(&name("host.dead.test"), Type::A) {
+			Err(Error::LameDelegation(zone)) => assert_eq!(zone, name("dead.test")),
+			other => panic!("{:?}", other),
+		}
+	}
+
+	#[test]
+	fn reports_nxdomain() {
+		assert!(matches!(
+			resolver().lookup(&name("nope.test"), Type::A),
+			Err(Error::Server(Rcode::NXDomain))
+		));
+		assert!(matches!(
+			resolver().lookup(&name("nope.example"), Type::A),
+			Err(Error::Server(Rcode::NXDomain))
+		));
+	}
+
+	#[test]
+	fn returns_no_records_for_nodata() {
+		let lookup = resolver().lookup(&name("www.test"), Type::AAAA).unwrap();
+		assert!(lookup.records.is_empty());
+	}
+
+	#[test]
+	fn keeps_primed_roots() {
+		let resolver = resolver();
+		let roots = resolver.roots(&mut |_| {});
+		assert_eq!(roots[0].name, name("a.root-servers.test"));
+		assert_eq!(*resolver.roots.lock().unwrap(), Some(roots));
+	}
+
+	#[test]
+	fn primes_again_after_failing() {
+		let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let hints = vec![NameServer {
+			name: name("a.root-servers.test."),
+			addrs: vec!["127.0.0.1".parse().unwrap()],
+		}];
+		let resolver = IterativeResolver::new(IterativeConfig {
+			root_hints: hints.clone(),
+			port: silent.local_addr().unwrap().port(),
+			timeout: Duration::from_millis(100),
+			..Default::default()
+		});
+		assert_eq!(resolver.roots(&mut |_| {}), hints);
+		assert!(resolver.roots.lock().unwrap().is_none());
+	}
+}
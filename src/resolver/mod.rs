@@ -0,0 +1,427 @@
+//! Looking up records by name, the way a stub resolver does
+//! https://tools.ietf.org/html/rfc1034#section-5
+
+use std::fmt::Write;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::thread;
+
+use crate::cache::Cache;
+use crate::client::{Client, ResolverConfig};
+use crate::error::{Error, Result};
+use crate::name::Name;
+use crate::records::{Class, RData, Type};
+use crate::wire::{DnsHeaderFlags, Message, Question, Rcode, ResourceRecord};
+
+pub mod iterative;
+
+pub use iterative::{IterativeConfig, IterativeResolver, Step};
+
+/// The records found for a name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lookup {
+	/// The name that was asked about, after applying the search list.
+	pub name: Name,
+	/// The CNAME records followed from `name`, in order.
+	pub cnames: Vec<ResourceRecord>,
+	/// The records of the type that was asked for, owned by the last name in
+	/// the CNAME chain. Empty if that name exists but has none.
+	pub records: Vec<ResourceRecord>,
+}
+
+impl Lookup {
+	/// The name the records belong to, at the end of the CNAME chain.
+	pub fn canonical_name(&self) -> &Name {
+		match self.cnames.last() {
+			Some(ResourceRecord {
+				rdata: RData::CNAME(target),
+				..
+			}) => target,
+			_ => &self.name,
+		}
+	}
+
+	/// How long the whole answer can be kept, which is as long as the
+	/// shortest lived record in it.
+	pub fn ttl(&self) -> Option<u32> {
+		self.cnames
+			.iter()
+			.chain(&self.records)
+			.map(|rr| rr.ttl)
+			.min()
+	}
+
+	/// The addresses in any A and AAAA records.
+	pub fn ip_addrs(&self) -> impl Iterator<Item = IpAddr> + '_ {
+		self.records.iter().filter_map(|rr| match rr.rdata {
+			RData::A(addr) => Some(IpAddr::V4(addr)),
+			RData::AAAA(addr) => Some(IpAddr::V6(addr)),
+			_ => None,
+		})
+	}
+}
+
+/// Looks up records using the recursive servers in a `ResolverConfig`.
+#[derive(Clone, Debug)]
+pub struct Resolver {
+	client: Client,
+	/// Shared with any clones.
+	cache: Option<Arc<Cache>>,
+}
+
+impl Resolver {
+	pub fn new(config: ResolverConfig) -> Resolver {
+		Resolver {
+			client: Client::new(config),
+			cache: None,
+		}
+	}
+
+	/// A resolver that keeps the responses it gets in `cache`, and answers
+	/// from there while they last. Responses the cache says are stale or due
+	/// to be prefetched are refreshed on a thread of their own.
+	pub fn with_cache(config: ResolverConfig, cache: Cache) -> Resolver {
+		Resolver {
+			client: Client::new(config),
+			cache: Some(Arc::new(cache)),
+		}
+	}
+
+	/// A resolver using the system configuration.
+	pub fn from_system() -> Result<Resolver> {
+		Ok(Resolver::new(ResolverConfig::from_system()?))
+	}
+
+	pub fn client(&self) -> &Client {
+		&self.client
+	}
+
+	pub fn cache(&self) -> Option<&Cache> {
+		self.cache.as_deref()
+	}
+
+	/// Look up the IPv4 and IPv6 addresses of a name. Both are asked for each
+	/// name from the search list, so they always belong to the same one.
+	pub fn lookup_ip(&self, name: &str) -> Result<Lookup> {
+		self.search(name, |candidate| {
+			let v4 = self.lookup_name(candidate, Type::A);
+			let v6 = self.lookup_name(candidate, Type::AAAA);
+			match (v4, v6) {
+				(Ok(mut v4), Ok(v6)) => {
+					v4.records.extend(v6.records);
+					Ok(v4)
+				}
+				(Ok(lookup), Err(_)) | (Err(_), Ok(lookup)) => Ok(lookup),
+				(Err(e), Err(_)) => Err(e),
+			}
+		})
+	}
+
+	/// Look up the records of one type for a name, which is completed with
+	/// the search list unless it ends with a dot.
+	pub fn lookup(&self, name: &str, rtype: Type) -> Result<Lookup> {
+		self.search(name, |candidate| self.lookup_name(candidate, rtype))
+	}
+
+	/// Try `lookup` on each candidate for `name` in turn until one has
+	/// records. If none do, the first that exists is returned with no
+	/// records, and otherwise the error for the last one.
+	/// https://man7.org/linux/man-pages/man5/resolv.conf.5.html
+	fn search<F>(&self, name: &str, lookup: F) -> Result<Lookup>
+	where
+		F: Fn(&Name) -> Result<Lookup>,
+	{
+		let mut empty = None;
+		let mut last_error = None;
+		for candidate in self.candidates(name)? {
+			match lookup(&candidate) {
+				Ok(lookup) if !lookup.records.is_empty() => return Ok(lookup),
+				Ok(lookup) => {
+					empty.get_or_insert(lookup);
+				}
+				// The name might exist under a different suffix.
+				Err(e @ Error::Server(_)) => last_error = Some(e),
+				Err(e) => return Err(e),
+			}
+		}
+		match (empty, last_error) {
+			(Some(lookup), _) => Ok(lookup),
+			(None, Some(e)) => Err(e),
+			(None, None) => Err(Error::Server(Rcode::NXDomain)),
+		}
+	}
+
+	/// Look up the names for an address in `in-addr.arpa` or `ip6.arpa`.
+	/// https://tools.ietf.org/html/rfc1035#section-3.5
+	/// https://tools.ietf.org/html/rfc3596#section-2.5
+	pub fn reverse_lookup(&self, addr: IpAddr) -> Result<Lookup> {
+		self.lookup_name(&reverse_name(addr), Type::PTR)
+	}
+
+	/// Look up an exact name, with no search list.
+	pub fn lookup_name(&self, name: &Name, rtype: Type) -> Result<Lookup> {
+		let question = Question {
+			name: name.clone(),
+			qtype: rtype,
+			qclass: Class::IN,
+		};
+		let cached = self.cache.as_ref().and_then(|cache| cache.get(&question));
+		let message = match cached {
+			Some(hit) => {
+				if hit.refresh {
+					let resolver = self.clone();
+					// If this fails, the cache asks for another try later.
+					thread::spawn(move || resolver.fetch(question));
+				}
+				hit.message
+			}
+			None => self.fetch(question)?,
+		};
+		message.check_rcode()?;
+		Ok(follow_cnames(&message, name, rtype))
+	}
+
+	/// Ask the servers, and keep the response in the cache if there is one.
+	fn fetch(&self, question: Question) -> Result<Message> {
+		let mut request = Message {
+			questions: vec![question],
+			..Default::default()
+		};
+		request.header.set_flags(DnsHeaderFlags::RECURSION_DESIRED);
+		let response = self.client.query(&request)?;
+		if let Some(cache) = &self.cache {
+			cache.insert(&response.message);
+		}
+		Ok(response.message)
+	}
+
+	/// The fully qualified names to try for `name`, in order. Names with
+	/// enough dots are tried as they are before the search list is used,
+	/// and names with fewer after.
+	fn candidates(&self, name: &str) -> Result<Vec<Name>> {
+		let config = self.client.config();
+		let (parsed, qualified) = Name::parse_qualified(name)?;
+		if qualified {
+			return Ok(vec![parsed]);
+		}
+
+		let searched = config
+			.search
+			.iter()
+			.filter_map(|domain| parsed.concat(domain).ok());
+		let dots = parsed.label_count().saturating_sub(1) as u32;
+		if dots >= config.ndots {
+			Ok(std::iter::once(parsed.clone()).chain(searched).collect())
+		} else {
+			Ok(searched.chain(std::iter::once(parsed.clone())).collect())
+		}
+	}
+}
+
+/// Collect the answers for `name`, following any CNAMEs in the answer
+/// section to find them.
+/// https://tools.ietf.org/html/rfc1034#section-3.6.2
+fn follow_cnames(message: &Message, name: &Name, rtype: Type) -> Lookup {
+	let mut current = name.clone();
+	let mut cnames = Vec::new();
+	// CNAMEs that form a loop would be followed forever, but a chain can't
+	// be longer than the answer section.
+	for _ in 0..=message.answers.len() {
+		let records: Vec<ResourceRecord> = message
+			.answers
+			.iter()
+			.filter(|rr| rr.name == current && (rr.rtype == rtype || rtype == Type::ANY))
+			.cloned()
+			.collect();
+		if !records.is_empty() || rtype == Type::CNAME {
+			return Lookup {
+				name: name.clone(),
+				cnames,
+				records,
+			};
+		}
+		let cname = message.answers.iter().find_map(|rr| match &rr.rdata {
+			RData::CNAME(target) if rr.name == current => Some((rr, target)),
+			_ => None,
+		});
+		match cname {
+			Some((rr, target)) => {
+				cnames.push(rr.clone());
+				current = target.clone();
+			}
+			None => break,
+		}
+	}
+	Lookup {
+		name: name.clone(),
+		cnames,
+		records: Vec::new(),
+	}
+}
+
+/// The name under which the PTR records for an address live.
+pub fn reverse_name(addr: IpAddr) -> Name {
+	let mut name = String::new();
+	match addr {
+		IpAddr::V4(addr) => {
+			for octet in addr.octets().iter().rev() {
+				write!(name, "{}.", octet).unwrap();
+			}
+			name.push_str("in-addr.arpa.");
+		}
+		IpAddr::V6(addr) => {
+			for octet in addr.octets().iter().rev() {
+				write!(name, "{:x}.{:x}.", octet & 0xf, octet >> 4).unwrap();
+			}
+			name.push_str("ip6.arpa.");
+		}
+	}
+	name.parse().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::net::UdpSocket;
+
+	fn name(s: &str) -> Name {
+		s.parse().unwrap()
+	}
+
+	fn rr(owner: &str, rdata: RData) -> ResourceRecord {
+		ResourceRecord {
+			name: name(owner),
+			rtype: rdata.rtype().unwrap(),
+			class: Class::IN,
+			ttl: 300,
+			rdata,
+		}
+	}
+
+	fn cname(owner: &str, target: &str) -> ResourceRecord {
+		rr(owner, RData::CNAME(name(target)))
+	}
+
+	#[test]
+	fn candidates_follow_ndots() {
+		let resolver = Resolver::new(ResolverConfig {
+			search: vec![name("a.example"), name("b.example")],
+			ndots: 2,
+			..Default::default()
+		});
+		let candidates = |s| resolver.candidates(s).unwrap();
+		let names = |names: &[&str]| names.iter().map(|s| name(s)).collect::<Vec<_>>();
+
+		assert_eq!(
+			candidates("www.x"),
+			names(&["www.x.a.example", "www.x.b.example", "www.x"])
+		);
+		assert_eq!(
+			candidates("www.x.y"),
+			names(&["www.x.y", "www.x.y.a.example", "www.x.y.b.example"])
+		);
+		assert_eq!(candidates("www.x."), names(&["www.x"]));
+		// An escaped backslash doesn't escape the dot after it.
+		assert_eq!(candidates("www\\\\."), names(&["www\\\\"]));
+		assert_eq!(
+			candidates("www\\."),
+			names(&["www\\..a.example", "www\\..b.example", "www\\."])
+		);
+	}
+
+	#[test]
+	fn addresses_come_from_one_candidate() {
+		// The search domain has only an A record, and the name as given only
+		// an AAAA record.
+		let records = [
+			rr("www.a.example", RData::A("192.0.2.1".parse().unwrap())),
+			rr("www", RData::AAAA("2001:db8::1".parse().unwrap())),
+		];
+		let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+		let addr = socket.local_addr().unwrap();
+		thread::spawn(move || {
+			let mut buf = [0; 512];
+			while let Ok((len, from)) = socket.recv_from(&mut buf) {
+				let request = Message::decode(&buf[..len]).unwrap();
+				let question = &request.questions[0];
+				let mut response = Message {
+					questions: request.questions.clone(),
+					answers: records
+						.iter()
+						.filter(|rr| rr.name == question.name && rr.rtype == question.qtype)
+						.cloned()
+						.collect(),
+					..Default::default()
+				};
+				response.header.id = request.header.id;
+				response.header.set_flags(DnsHeaderFlags::RESPONSE);
+				if !records.iter().any(|rr| rr.name == question.name) {
+					response.header.set_rcode(Rcode::NXDomain);
+				}
+				socket.send_to(&response.encode().unwrap(), from).unwrap();
+			}
+		});
+
+		let resolver = Resolver::new(ResolverConfig {
+			nameservers: vec![addr],
+			search: vec![name("a.example")],
+			..Default::default()
+		});
+		let lookup = resolver.lookup_ip("www").unwrap();
+		assert_eq!(lookup.name, name("www.a.example"));
+		assert_eq!(
+			lookup.ip_addrs().collect::<Vec<_>>(),
+			vec!["192.0.2.1".parse::<IpAddr>().unwrap()]
+		);
+	}
+
+	#[test]
+	fn follows_cname_chains() {
+		let message = Message {
+			answers: vec![
+				cname("www.example", "web.example"),
+				rr("host.example", RData::A("192.0.2.1".parse().unwrap())),
+				cname("web.example", "host.example"),
+			],
+			..Default::default()
+		};
+		let lookup = follow_cnames(&message, &name("www.example"), Type::A);
+		assert_eq!(lookup.cnames.len(), 2);
+		assert_eq!(lookup.canonical_name(), &name("host.example"));
+		assert_eq!(
+			lookup.ip_addrs().collect::<Vec<_>>(),
+			vec!["192.0.2.1".parse::<IpAddr>().unwrap()]
+		);
+
+		let lookup = follow_cnames(&message, &name("www.example"), Type::CNAME);
+		assert!(lookup.cnames.is_empty());
+		assert_eq!(lookup.records, vec![cname("www.example", "web.example")]);
+	}
+
+	#[test]
+	fn stops_following_cname_loops() {
+		let message = Message {
+			answers: vec![
+				cname("a.example", "b.example"),
+				cname("b.example", "a.example"),
+			],
+			..Default::default()
+		};
+		let lookup = follow_cnames(&message, &name("a.example"), Type::A);
+		assert!(lookup.records.is_empty());
+		assert_eq!(lookup.cnames.len(), message.answers.len() + 1);
+	}
+
+	#[test]
+	fn reverse_names() {
+		assert_eq!(
+			reverse_name("192.0.2.1".parse().unwrap()),
+			name("1.2.0.192.in-addr.arpa")
+		);
+		// https://tools.ietf.org/html/rfc3596#section-2.5
+		assert_eq!(
+			reverse_name("4321:0:1:2:3:4:567:89ab".parse().unwrap()),
+			name("b.a.9.8.7.6.5.0.4.0.0.0.3.0.0.0.2.0.0.0.1.0.0.0.0.0.0.0.1.2.3.4.IP6.ARPA")
+		);
+	}
+}
@@ -0,0 +1,896 @@
+//! Encoding and decoding of DNS messages in their wire format
+//! https://tools.ietf.org/html/rfc1035#section-4
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+use crate::edns::Edns;
+use crate::error::{Error, Result};
+use crate::name::Name;
+use crate::records::{Class, RData, Type};
+
+/// https://tools.ietf.org/html/rfc1035#section-2.3.4
+pub const MAX_LABEL_LEN: usize = 63;
+pub const MAX_NAME_LEN: usize = 255;
+
+/// A cursor over a complete DNS message.
+///
+/// The whole message is kept around rather than just the unread part because
+/// names can refer back to earlier parts of it.
+pub struct Reader<'a> {
+	buf: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	pub fn new(buf: &'a [u8]) -> Reader<'a> {
+		Reader { buf, pos: 0 }
+	}
+
+	pub fn position(&self) -> usize {
+		self.pos
+	}
+
+	pub fn remaining(&self) -> usize {
+		self.buf.len() - self.pos
+	}
+
+	pub fn read_bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+		if self.remaining() < n {
+			return Err(Error::Truncated);
+		}
+		let bytes = &self.buf[self.pos..self.pos + n];
+		self.pos += n;
+		Ok(bytes)
+	}
+
+	pub fn read_u8(&mut self) -> Result<u8> {
+		Ok(self.read_bytes(1)?[0])
+	}
+
+	pub fn read_u16(&mut self) -> Result<u16> {
+		let b = self.read_bytes(2)?;
+		Ok(u16::from_be_bytes([b[0], b[1]]))
+	}
+
+	pub fn read_u32(&mut self) -> Result<u32> {
+		let b = self.read_bytes(4)?;
+		Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+	}
+
+	/// https://tools.ietf.org/html/rfc1035#section-3.3
+	pub fn read_character_string(&mut self) -> Result<Vec<u8>> {
+		let len = self.read_u8()?;
+		Ok(self.read_bytes(len as usize)?.to_vec())
+	}
+
+	/// Read a domain name.
+	///
+	/// Compression pointers are followed, but each one must point strictly
+	/// before the previous one. Real encoders only ever point back at names
+	/// they have already written, and this guarantees that a malicious message
+	/// can't make us loop forever.
+	/// https://tools.ietf.org/html/rfc1035#section-3.1
+	/// https://tools.ietf.org/html/rfc1035#section-4.1.4
+	pub fn read_name(&mut self) -> Result<Name> {
+		let mut labels = Vec::new();
+		// Length of the name in uncompressed wire format, including the root label.
+		let mut wire_len = 1;
+		let mut pos = self.pos;
+		// Start of the run of labels we are currently reading.
+		let mut segment_start = pos;
+		// Where the name ends in the message, set at the first pointer.
+		let mut end = None;
+
+		loop {
+			let len = *self.buf.get(pos).ok_or(Error::Truncated)?;
+			match len & 0xc0 {
+				0x00 => {}
+				0xc0 => {
+					let low = *self.buf.get(pos + 1).ok_or(Error::Truncated)?;
+					let target = ((len as usize & 0x3f) << 8) | low as usize;
+					if target >= self.buf.len() {
+						return Err(Error::PointerOutOfBounds(target));
+					}
+					if target >= segment_start {
+						return Err(Error::CompressionLoop(target));
+					}
+					end.get_or_insert(pos + 2);
+					pos = target;
+					segment_start = target;
+					continue;
+				}
+				_ => return Err(Error::BadLabelType(len)),
+			}
+
+			pos += 1;
+			if len == 0 {
+				break;
+			}
+
+			wire_len += 1 + len as usize;
+			if wire_len > MAX_NAME_LEN {
+				return Err(Error::NameTooLong);
+			}
+
+			let label = self
+				.buf
+				.get(pos..pos + len as usize)
+				.ok_or(Error::Truncated)?;
+			pos += len as usize;
+
+			labels.push(label.to_vec());
+		}
+
+		self.pos = end.unwrap_or(pos);
+		Name::from_labels(labels)
+	}
+}
+
+/// A buffer for building a DNS message.
+///
+/// Every name written is remembered by offset so that later names sharing a
+/// suffix with it can be replaced by a compression pointer.
+/// https://tools.ietf.org/html/rfc1035#section-4.1.4
+#[derive(Default)]
+pub struct Writer {
+	buf: Vec<u8>,
+	/// Lowercased wire form of each name suffix written so far, and its offset.
+	names: HashMap<Vec<u8>, u16>,
+}
+
+impl Writer {
+	pub fn new() -> Writer {
+		Default::default()
+	}
+
+	pub fn len(&self) -> usize {
+		self.buf.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.buf.is_empty()
+	}
+
+	pub fn write_bytes(&mut self, bytes: &[u8]) {
+		self.buf.extend_from_slice(bytes);
+	}
+
+	pub fn write_u8(&mut self, x: u8) {
+		self.buf.push(x);
+	}
+
+	pub fn write_u16(&mut self, x: u16) {
+		self.write_bytes(&x.to_be_bytes());
+	}
+
+	pub fn write_u32(&mut self, x: u32) {
+		self.write_bytes(&x.to_be_bytes());
+	}
+
+	/// Overwrite a u16 written earlier, for lengths that aren't known until
+	/// after what they describe has been written.
+	pub fn patch_u16(&mut self, pos: usize, x: u16) {
+		self.buf[pos..pos + 2].copy_from_slice(&x.to_be_bytes());
+	}
+
+	/// https://tools.ietf.org/html/rfc1035#section-3.3
+	pub fn write_character_string(&mut self, s: &[u8]) -> Result<()> {
+		if s.len() > 0xff {
+			return Err(Error::StringTooLong);
+		}
+		self.write_u8(s.len() as u8);
+		self.write_bytes(s);
+		Ok(())
+	}
+
+	/// Write a name, compressing it against the names already in the message.
+	pub fn write_name(&mut self, name: &Name) {
+		self.write_labels(name, true)
+	}
+
+	/// Write a name without compressing it, for record types where
+	/// compression isn't allowed. It can still be the target of later
+	/// pointers.
+	/// https://tools.ietf.org/html/rfc3597#section-4
+	pub fn write_name_uncompressed(&mut self, name: &Name) {
+		self.write_labels(name, false)
+	}
+
+	fn write_labels(&mut self, name: &Name, compress: bool) {
+		let labels: Vec<&[u8]> = name.labels().collect();
+		for i in 0..labels.len() {
+			let key = suffix_key(&labels[i..]);
+			if let Some(&offset) = self.names.get(&key) {
+				if compress {
+					self.write_u16(0xc000 | offset);
+					return;
+				}
+			} else if self.buf.len() < 0x4000 {
+				// Pointers only have 14 bits for the offset.
+				self.names.insert(key, self.buf.len() as u16);
+			}
+			self.buf.push(labels[i].len() as u8);
+			self.buf.extend_from_slice(labels[i]);
+		}
+		self.buf.push(0);
+	}
+
+	pub fn into_bytes(self) -> Vec<u8> {
+		self.buf
+	}
+}
+
+/// Names compare case-insensitively, so compression should too.
+/// https://tools.ietf.org/html/rfc1035#section-2.3.3
+fn suffix_key(labels: &[&[u8]]) -> Vec<u8> {
+	let mut key = Vec::new();
+	for label in labels {
+		key.push(label.len() as u8);
+		key.extend(label.iter().map(u8::to_ascii_lowercase));
+	}
+	key
+}
+
+bitflags! {
+	/// The single-bit flags in the header. The OPCODE and RCODE fields share
+	/// the same 16 bits, and are accessed through `DnsHeader` instead.
+	/// https://tools.ietf.org/html/rfc1035#section-4.1.1
+	/// https://tools.ietf.org/html/rfc4035#section-3.2
+	pub struct DnsHeaderFlags : u16 {
+		const RESPONSE = 0x8000;
+		const AUTHORITATIVE = 0x0400;
+		const TRUNCATED = 0x0200;
+		const RECURSION_DESIRED = 0x0100;
+		const RECURSION_AVAILABLE = 0x0080;
+		/// Reserved, must be zero.
+		const Z = 0x0040;
+		const AUTHENTIC_DATA = 0x0020;
+		const CHECKING_DISABLED = 0x0010;
+	}
+}
+
+/// The flags as `dig` shows them, like `qr rd ra`.
+impl fmt::Display for DnsHeaderFlags {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let names = [
+			(DnsHeaderFlags::RESPONSE, "qr"),
+			(DnsHeaderFlags::AUTHORITATIVE, "aa"),
+			(DnsHeaderFlags::TRUNCATED, "tc"),
+			(DnsHeaderFlags::RECURSION_DESIRED, "rd"),
+			(DnsHeaderFlags::RECURSION_AVAILABLE, "ra"),
+			(DnsHeaderFlags::Z, "z"),
+			(DnsHeaderFlags::AUTHENTIC_DATA, "ad"),
+			(DnsHeaderFlags::CHECKING_DISABLED, "cd"),
+		];
+		let mut first = true;
+		for (flag, name) in names.iter() {
+			if self.contains(*flag) {
+				if !first {
+					write!(f, " ")?;
+				}
+				write!(f, "{}", name)?;
+				first = false;
+			}
+		}
+		Ok(())
+	}
+}
+
+const OPCODE_SHIFT: u16 = 11;
+const OPCODE_MASK: u16 = 0x7800;
+const RCODE_MASK: u16 = 0x000f;
+
+/// https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-5
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Opcode {
+	Query,
+	IQuery,
+	Status,
+	Notify,
+	Update,
+	Dso,
+	Unknown(u8),
+}
+
+impl From<u8> for Opcode {
+	fn from(x: u8) -> Opcode {
+		match x {
+			0 => Opcode::Query,
+			1 => Opcode::IQuery,
+			2 => Opcode::Status,
+			4 => Opcode::Notify,
+			5 => Opcode::Update,
+			6 => Opcode::Dso,
+			_ => Opcode::Unknown(x),
+		}
+	}
+}
+
+impl From<Opcode> for u8 {
+	fn from(op: Opcode) -> u8 {
+		match op {
+			Opcode::Query => 0,
+			Opcode::IQuery => 1,
+			Opcode::Status => 2,
+			Opcode::Notify => 4,
+			Opcode::Update => 5,
+			Opcode::Dso => 6,
+			Opcode::Unknown(x) => x,
+		}
+	}
+}
+
+impl fmt::Display for Opcode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Opcode::Query => write!(f, "QUERY"),
+			Opcode::IQuery => write!(f, "IQUERY"),
+			Opcode::Status => write!(f, "STATUS"),
+			Opcode::Notify => write!(f, "NOTIFY"),
+			Opcode::Update => write!(f, "UPDATE"),
+			Opcode::Dso => write!(f, "DSO"),
+			Opcode::Unknown(x) => write!(f, "OPCODE{}", x),
+		}
+	}
+}
+
+/// https://www.iana.org/assignments/dns-parameters/dns-parameters.xhtml#dns-parameters-6
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rcode {
+	NoError,
+	FormErr,
+	ServFail,
+	NXDomain,
+	NotImp,
+	Refused,
+	YXDomain,
+	YXRRSet,
+	NXRRSet,
+	NotAuth,
+	NotZone,
+	/// https://tools.ietf.org/html/rfc6891#section-9
+	BadVers,
+	/// https://tools.ietf.org/html/rfc7873#section-8
+	BadCookie,
+	Unknown(u16),
+}
+
+impl From<u16> for Rcode {
+	fn from(x: u16) -> Rcode {
+		match x {
+			0 => Rcode::NoError,
+			1 => Rcode::FormErr,
+			2 => Rcode::ServFail,
+			3 => Rcode::NXDomain,
+			4 => Rcode::NotImp,
+			5 => Rcode::Refused,
+			6 => Rcode::YXDomain,
+			7 => Rcode::YXRRSet,
+			8 => Rcode::NXRRSet,
+			9 => Rcode::NotAuth,
+			10 => Rcode::NotZone,
+			16 => Rcode::BadVers,
+			23 => Rcode::BadCookie,
+			_ => Rcode::Unknown(x),
+		}
+	}
+}
+
+impl From<Rcode> for u16 {
+	fn from(rcode: Rcode) -> u16 {
+		match rcode {
+			Rcode::NoError => 0,
+			Rcode::FormErr => 1,
+			Rcode::ServFail => 2,
+			Rcode::NXDomain => 3,
+			Rcode::NotImp => 4,
+			Rcode::Refused => 5,
+			Rcode::YXDomain => 6,
+			Rcode::YXRRSet => 7,
+			Rcode::NXRRSet => 8,
+			Rcode::NotAuth => 9,
+			Rcode::NotZone => 10,
+			Rcode::BadVers => 16,
+			Rcode::BadCookie => 23,
+			Rcode::Unknown(x) => x,
+		}
+	}
+}
+
+impl fmt::Display for Rcode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Rcode::NoError => write!(f, "NOERROR"),
+			Rcode::FormErr => write!(f, "FORMERR"),
+			Rcode::ServFail => write!(f, "SERVFAIL"),
+			Rcode::NXDomain => write!(f, "NXDOMAIN"),
+			Rcode::NotImp => write!(f, "NOTIMP"),
+			Rcode::Refused => write!(f, "REFUSED"),
+			Rcode::YXDomain => write!(f, "YXDOMAIN"),
+			Rcode::YXRRSet => write!(f, "YXRRSET"),
+			Rcode::NXRRSet => write!(f, "NXRRSET"),
+			Rcode::NotAuth => write!(f, "NOTAUTH"),
+			Rcode::NotZone => write!(f, "NOTZONE"),
+			Rcode::BadVers => write!(f, "BADVERS"),
+			Rcode::BadCookie => write!(f, "BADCOOKIE"),
+			Rcode::Unknown(x) => write!(f, "RCODE{}", x),
+		}
+	}
+}
+
+/// https://tools.ietf.org/html/rfc1035#section-4.1.1
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DnsHeader {
+	pub id: u16,
+	pub flags: u16,
+	pub qcount: u16,
+	pub ancount: u16,
+	pub nscount: u16,
+	pub arcount: u16,
+}
+
+impl DnsHeader {
+	pub fn flags(&self) -> DnsHeaderFlags {
+		DnsHeaderFlags::from_bits_truncate(self.flags)
+	}
+
+	/// Set the single-bit flags, leaving OPCODE and RCODE alone.
+	pub fn set_flags(&mut self, flags: DnsHeaderFlags) {
+		self.flags = (self.flags & (OPCODE_MASK | RCODE_MASK)) | flags.bits();
+	}
+
+	pub fn opcode(&self) -> Opcode {
+		Opcode::from(((self.flags & OPCODE_MASK) >> OPCODE_SHIFT) as u8)
+	}
+
+	pub fn set_opcode(&mut self, opcode: Opcode) {
+		let bits = (u8::from(opcode) as u16) << OPCODE_SHIFT;
+		self.flags = (self.flags & !OPCODE_MASK) | (bits & OPCODE_MASK);
+	}
+
+	pub fn rcode(&self) -> Rcode {
+		Rcode::from(self.flags & RCODE_MASK)
+	}
+
+	pub fn set_rcode(&mut self, rcode: Rcode) {
+		self.flags = (self.flags & !RCODE_MASK) | (u16::from(rcode) & RCODE_MASK);
+	}
+
+	pub fn decode(r: &mut Reader) -> Result<DnsHeader> {
+		Ok(DnsHeader {
+			id: r.read_u16()?,
+			flags: r.read_u16()?,
+			qcount: r.read_u16()?,
+			ancount: r.read_u16()?,
+			nscount: r.read_u16()?,
+			arcount: r.read_u16()?,
+		})
+	}
+
+	pub fn encode(&self, w: &mut Writer) {
+		w.write_u16(self.id);
+		w.write_u16(self.flags);
+		w.write_u16(self.qcount);
+		w.write_u16(self.ancount);
+		w.write_u16(self.nscount);
+		w.write_u16(self.arcount);
+	}
+}
+
+/// https://tools.ietf.org/html/rfc1035#section-4.1.2
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Question {
+	pub name: Name,
+	pub qtype: Type,
+	pub qclass: Class,
+}
+
+impl Question {
+	pub fn decode(r: &mut Reader) -> Result<Question> {
+		Ok(Question {
+			name: r.read_name()?,
+			qtype: Type::from(r.read_u16()?),
+			qclass: Class::from(r.read_u16()?),
+		})
+	}
+
+	pub fn encode(&self, w: &mut Writer) -> Result<()> {
+		w.write_name(&self.name);
+		w.write_u16(u16::from(self.qtype));
+		w.write_u16(u16::from(self.qclass));
+		Ok(())
+	}
+}
+
+/// Shown the way `dig` shows the question section, like a record with no TTL
+/// or data, commented out.
+impl fmt::Display for Question {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, ";{}\t\t{}\t{}", self.name, self.qclass, self.qtype)
+	}
+}
+
+/// https://tools.ietf.org/html/rfc1035#section-4.1.3
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceRecord {
+	pub name: Name,
+	pub rtype: Type,
+	pub class: Class,
+	pub ttl: u32,
+	pub rdata: RData,
+}
+
+impl ResourceRecord {
+	pub fn decode(r: &mut Reader) -> Result<ResourceRecord> {
+		let name = r.read_name()?;
+		let rtype = Type::from(r.read_u16()?);
+		let class = Class::from(r.read_u16()?);
+		let ttl = r.read_u32()?;
+		let rdlength = r.read_u16()? as usize;
+		if r.remaining() < rdlength {
+			return Err(Error::BadRdLength);
+		}
+		let rdata = RData::decode(rtype, r, rdlength)?;
+		Ok(ResourceRecord {
+			name,
+			rtype,
+			class,
+			ttl,
+			rdata,
+		})
+	}
+
+	pub fn encode(&self, w: &mut Writer) -> Result<()> {
+		w.write_name(&self.name);
+		w.write_u16(u16::from(self.rtype));
+		w.write_u16(u16::from(self.class));
+		w.write_u32(self.ttl);
+
+		let len_pos = w.len();
+		w.write_u16(0);
+		self.rdata.encode(w)?;
+		let rdlength = w.len() - len_pos - 2;
+		if rdlength > 0xffff {
+			return Err(Error::BadRdLength);
+		}
+		w.patch_u16(len_pos, rdlength as u16);
+		Ok(())
+	}
+}
+
+/// In zone file format.
+/// https://tools.ietf.org/html/rfc1035#section-5.1
+impl fmt::Display for ResourceRecord {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{}\t{}\t{}\t{}\t{}",
+			self.name, self.ttl, self.class, self.rtype, self.rdata
+		)
+	}
+}
+
+/// https://tools.ietf.org/html/rfc1035#section-4.1
+#[derive(Clone, Debug, Default)]
+pub struct Message {
+	pub header: DnsHeader,
+	pub questions: Vec<Question>,
+	pub answers: Vec<ResourceRecord>,
+	pub authorities: Vec<ResourceRecord>,
+	pub additionals: Vec<ResourceRecord>,
+}
+
+impl Message {
+	pub fn decode(buf: &[u8]) -> Result<Message> {
+		let mut r = Reader::new(buf);
+		let header = DnsHeader::decode(&mut r)?;
+
+		let questions = (0..header.qcount)
+			.map(|_| Question::decode(&mut r))
+			.collect::<Result<_>>()?;
+		let answers = decode_records(&mut r, header.ancount)?;
+		let authorities = decode_records(&mut r, header.nscount)?;
+		let additionals = decode_records(&mut r, header.arcount)?;
+
+		if r.remaining() != 0 {
+			return Err(Error::TrailingData);
+		}
+
+		Ok(Message {
+			header,
+			questions,
+			answers,
+			authorities,
+			additionals,
+		})
+	}
+
+	/// The EDNS information from the OPT record in the additional section.
+	pub fn edns(&self) -> Option<Edns> {
+		self.additionals.iter().find_map(Edns::from_record)
+	}
+
+	/// Replace the OPT record, or remove it if `edns` is `None`.
+	pub fn set_edns(&mut self, edns: Option<Edns>) {
+		self.additionals.retain(|rr| rr.rtype != Type::OPT);
+		if let Some(edns) = edns {
+			self.additionals.push(edns.to_record());
+		}
+	}
+
+	/// The full RCODE, including the upper bits from the OPT record.
+	/// https://tools.ietf.org/html/rfc6891#section-6.1.3
+	pub fn rcode(&self) -> Rcode {
+		let upper = self.edns().map_or(0, |edns| edns.extended_rcode as u16);
+		Rcode::from(upper << 4 | u16::from(self.header.rcode()))
+	}
+
+	/// Turn an error RCODE, like NXDOMAIN or SERVFAIL, into an `Error`.
+	pub fn check_rcode(&self) -> Result<()> {
+		match self.rcode() {
+			Rcode::NoError => Ok(()),
+			rcode => Err(Error::Server(rcode)),
+		}
+	}
+
+	/// Encode the message, compressing names where possible. The section
+	/// counts in the header are taken from the sections themselves.
+	pub fn encode(&self) -> Result<Vec<u8>> {
+		let header = DnsHeader {
+			qcount: self.questions.len() as u16,
+			ancount: self.answers.len() as u16,
+			nscount: self.authorities.len() as u16,
+			arcount: self.additionals.len() as u16,
+			..self.header
+		};
+
+		let mut w = Writer::new();
+		header.encode(&mut w);
+		for q in &self.questions {
+			q.encode(&mut w)?;
+		}
+		for rr in self
+			.answers
+			.iter()
+			.chain(&self.authorities)
+			.chain(&self.additionals)
+		{
+			rr.encode(&mut w)?;
+		}
+		Ok(w.into_bytes())
+	}
+}
+
+/// Shown the way `dig` shows it: the header, the OPT record as a
+/// pseudosection, and then each section that has anything in it.
+impl fmt::Display for Message {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(
+			f,
+			";; ->>HEADER<<- opcode: {}, status: {}, id: {}",
+			self.header.opcode(),
+			self.rcode(),
+			self.header.id
+		)?;
+		writeln!(
+			f,
+			";; flags: {}; QUERY: {}, ANSWER: {}, AUTHORITY: {}, ADDITIONAL: {}",
+			self.header.flags(),
+			self.questions.len(),
+			self.answers.len(),
+			self.authorities.len(),
+			self.additionals.len()
+		)?;
+
+		if let Some(edns) = self.edns() {
+			writeln!(f, "\n;; OPT PSEUDOSECTION:\n{}", edns)?;
+		}
+		if !self.questions.is_empty() {
+			writeln!(f, "\n;; QUESTION SECTION:")?;
+			for q in &self.questions {
+				writeln!(f, "{}", q)?;
+			}
+		}
+		let sections = [
+			("ANSWER", &self.answers),
+			("AUTHORITY", &self.authorities),
+			("ADDITIONAL", &self.additionals),
+		];
+		for (section, records) in sections.iter() {
+			let mut records = records.iter().filter(|rr| rr.rtype != Type::OPT).peekable();
+			if records.peek().is_some() {
+				writeln!(f, "\n;; {} SECTION:", section)?;
+				for rr in records {
+					writeln!(f, "{}", rr)?;
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+fn decode_records(r: &mut Reader, count: u16) -> Result<Vec<ResourceRecord>> {
+	(0..count).map(|_| ResourceRecord::decode(r)).collect()
+}
+
+/// Format raw bytes 16 to a line, with their offsets, for debugging.
+pub fn hexdump(data: &[u8]) -> String {
+	let mut s = String::new();
+	for (i, d) in data.chunks(16).enumerate() {
+		write!(s, "{:04x}  ", i * 16).unwrap();
+		for ch in d.chunks(8) {
+			for x in ch {
+				write!(s, "{:02x} ", x).unwrap();
+			}
+			s.push(' ');
+		}
+		s.push('\n');
+	}
+	s
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn name(s: &str) -> Name {
+		s.parse().unwrap()
+	}
+
+	fn read_name(buf: &[u8], pos: usize) -> Result<Name> {
+		let mut r = Reader::new(buf);
+		r.read_bytes(pos)?;
+		r.read_name()
+	}
+
+	#[test]
+	fn reads_compressed_names() {
+		let buf = b"\x07example\x03com\x00\x03www\xc0\x00";
+		let mut r = Reader::new(buf);
+		assert_eq!(r.read_name().unwrap(), name("example.com"));
+		assert_eq!(r.read_name().unwrap(), name("www.example.com"));
+		assert_eq!(r.remaining(), 0);
+	}
+
+	#[test]
+	fn rejects_self_pointer() {
+		assert!(matches!(
+			read_name(b"\xc0\x00", 0),
+			Err(Error::CompressionLoop(0))
+		));
+		assert!(matches!(
+			read_name(b"\x01a\xc0\x00", 0),
+			Err(Error::CompressionLoop(0))
+		));
+	}
+
+	#[test]
+	fn rejects_forward_pointer() {
+		assert!(matches!(
+			read_name(b"\xc0\x02\x00", 0),
+			Err(Error::CompressionLoop(2))
+		));
+	}
+
+	#[test]
+	fn rejects_pointer_loop() {
+		// The second name points at the first, which points at the second.
+		assert!(matches!(
+			read_name(b"\x01a\xc0\x04\x01b\xc0\x00", 4),
+			Err(Error::CompressionLoop(4))
+		));
+	}
+
+	#[test]
+	fn rejects_pointer_out_of_bounds() {
+		assert!(matches!(
+			read_name(b"\x00\xc0\x10", 1),
+			Err(Error::PointerOutOfBounds(0x10))
+		));
+	}
+
+	#[test]
+	fn rejects_reserved_label_types() {
+		assert!(matches!(
+			read_name(b"\x41a\x00", 0),
+			Err(Error::BadLabelType(0x41))
+		));
+		assert!(matches!(
+			read_name(b"\x80\x00", 0),
+			Err(Error::BadLabelType(0x80))
+		));
+	}
+
+	#[test]
+	fn rejects_truncated_names() {
+		assert!(matches!(read_name(b"\x03ab", 0), Err(Error::Truncated)));
+		assert!(matches!(read_name(b"\x00\xc0", 1), Err(Error::Truncated)));
+	}
+
+	#[test]
+	fn rejects_long_names_through_pointers() {
+		// Each name is a 63 byte label followed by a pointer to the previous
+		// one, so no part of the message is over 255 bytes on its own.
+		let mut buf = vec![0];
+		let mut previous = 0;
+		for _ in 0..4 {
+			let start = buf.len();
+			buf.push(63);
+			buf.extend_from_slice(&[b'a'; 63]);
+			buf.extend_from_slice(&(0xc000 | previous as u16).to_be_bytes());
+			previous = start;
+		}
+		assert!(read_name(&buf, 1 + 66 * 2).is_ok());
+		assert!(matches!(read_name(&buf, previous), Err(Error::NameTooLong)));
+	}
+
+	#[test]
+	fn writer_compresses_case_insensitively() {
+		let mut w = Writer::new();
+		w.write_name(&name("Example.COM"));
+		w.write_name(&name("www.example.com"));
+		w.write_name_uncompressed(&name("mail.example.com"));
+		w.write_name(&name("example.com"));
+		let buf = w.into_bytes();
+		assert_eq!(
+			&buf[..],
+			&b"\x07Example\x03COM\x00\x03www\xc0\x00\x04mail\x07example\x03com\x00\xc0\x00"[..]
+		);
+
+		let mut r = Reader::new(&buf);
+		for expected in &[
+			"example.com",
+			"www.example.com",
+			"mail.example.com",
+			"example.com",
+		] {
+			assert_eq!(r.read_name().unwrap(), name(expected));
+		}
+		assert_eq!(r.remaining(), 0);
+	}
+
+	#[test]
+	fn writer_round_trips_the_root() {
+		let mut w = Writer::new();
+		w.write_name(&Name::root());
+		let buf = w.into_bytes();
+		assert_eq!(&buf[..], b"\x00");
+		assert!(Reader::new(&buf).read_name().unwrap().is_root());
+	}
+
+	#[test]
+	fn message_round_trips() {
+		let mut message = Message {
+			questions: vec![Question {
+				name: name("www.example.com"),
+				qtype: Type::A,
+				qclass: Class::IN,
+			}],
+			..Default::default()
+		};
+		message.header.id = 0x1234;
+		message.header.set_flags(DnsHeaderFlags::RESPONSE);
+		message.answers.push(ResourceRecord {
+			name: name("www.example.com"),
+			rtype: Type::CNAME,
+			class: Class::IN,
+			ttl: 300,
+			rdata: RData::CNAME(name("web.example.com")),
+		});
+		message.answers.push(ResourceRecord {
+			name: name("web.example.com"),
+			rtype: Type::A,
+			class: Class::IN,
+			ttl: 60,
+			rdata: RData::A("192.0.2.1".parse().unwrap()),
+		});
+
+		let buf = message.encode().unwrap();
+		let decoded = Message::decode(&buf).unwrap();
+		assert_eq!(decoded.header.id, 0x1234);
+		assert_eq!(decoded.questions, message.questions);
+		assert_eq!(decoded.answers, message.answers);
+		assert_eq!(decoded.encode().unwrap(), buf);
+	}
+}